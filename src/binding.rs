@@ -0,0 +1,147 @@
+use crate::{
+    config,
+    gesture::{Gesture, GestureDevice},
+    keys::key_code,
+};
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+
+/// in degree, how far the pad ring should turn to trigger once
+const RING_STEP: f64 = 15.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Key(u32),
+    PadButton(u32),
+    PadRing {
+        ring: u32,
+        clockwise: bool,
+    },
+    /// `None` match any for binding
+    Gesture {
+        gesture: Gesture,
+        fingers: Option<u32>,
+        device: Option<GestureDevice>,
+    },
+}
+
+impl Trigger {
+    /// the trigger, the mode scope and tasks
+    pub fn from_config(
+        binding: config::Binding,
+    ) -> Result<(Self, Option<String>, Vec<config::Task>)> {
+        let binding = match binding {
+            config::Binding::Key(key, mode, tasks) => {
+                let code = key_code(&key).ok_or_else(|| anyhow!("Unknown key: {key}"))?;
+                (Self::Key(code), mode, tasks)
+            }
+            config::Binding::PadButton(button, mode, tasks) => {
+                (Self::PadButton(button), mode, tasks)
+            }
+            config::Binding::PadRing(ring, direction, mode, tasks) => {
+                let clockwise = match direction.as_str() {
+                    "cw" => true,
+                    "ccw" => false,
+                    s => return Err(anyhow!("Unknown value for pad ring direction: {s}")),
+                };
+                (Self::PadRing { ring, clockwise }, mode, tasks)
+            }
+            config::Binding::Swipe(direction, fingers, device, mode, tasks) => {
+                let gesture = Gesture::Swipe(direction.parse()?);
+                (Self::gesture(gesture, fingers, device)?, mode, tasks)
+            }
+            config::Binding::Pinch(direction, fingers, device, mode, tasks) => {
+                let gesture = match direction.as_str() {
+                    "in" => Gesture::PinchIn,
+                    "out" => Gesture::PinchOut,
+                    s => return Err(anyhow!("Unknown value for pinch direction: {s}")),
+                };
+                (Self::gesture(gesture, fingers, device)?, mode, tasks)
+            }
+            config::Binding::Hold(fingers, device, mode, tasks) => {
+                (Self::gesture(Gesture::Hold, fingers, device)?, mode, tasks)
+            }
+        };
+        Ok(binding)
+    }
+
+    fn gesture(gesture: Gesture, fingers: Option<u32>, device: Option<String>) -> Result<Self> {
+        Ok(Self::Gesture {
+            gesture,
+            fingers,
+            device: device.as_deref().map(str::parse).transpose()?,
+        })
+    }
+
+    /// `self` is from binding, gesture without `fingers` match multi-finger only
+    pub fn matches(&self, event: &Self) -> bool {
+        match (self, event) {
+            (
+                Self::Gesture {
+                    gesture,
+                    fingers,
+                    device,
+                },
+                Self::Gesture {
+                    gesture: event_gesture,
+                    fingers: Some(event_fingers),
+                    device: Some(event_device),
+                },
+            ) => {
+                gesture == event_gesture
+                    && fingers.map_or(*event_fingers >= 2, |fingers| fingers == *event_fingers)
+                    && device.is_none_or(|device| device == *event_device)
+            }
+            (binding, event) => binding == event,
+        }
+    }
+}
+
+/// turn the absolute pad ring position into steps
+#[derive(Debug, Default)]
+pub struct RingTracker {
+    /// last position and accumulated rotation, counterclockwise
+    rings: BTreeMap<u32, (f64, f64)>,
+}
+
+impl RingTracker {
+    /// `position` is `-1` when the finger is lifted, return `Some(clockwise)` per step
+    pub fn update(&mut self, ring: u32, position: f64) -> Option<bool> {
+        if position < 0. {
+            self.rings.remove(&ring);
+            return None;
+        }
+        let Some((last, delta)) = self.rings.get_mut(&ring) else {
+            self.rings.insert(ring, (position, 0.));
+            return None;
+        };
+        *delta += (position - *last + 180.).rem_euclid(360.) - 180.;
+        *last = position;
+        if delta.abs() < RING_STEP {
+            return None;
+        }
+        let clockwise = *delta < 0.;
+        *delta -= delta.signum() * RING_STEP;
+        Some(clockwise)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_step() {
+        let mut tracker = RingTracker::default();
+        assert_eq!(tracker.update(0, 350.), None);
+        assert_eq!(tracker.update(0, 0.), None);
+        // cross 0 counterclockwise
+        assert_eq!(tracker.update(0, 6.), Some(false));
+        assert_eq!(tracker.update(0, 0.), None);
+        assert_eq!(tracker.update(0, 340.), Some(true));
+        // finger lifted
+        assert_eq!(tracker.update(0, -1.), None);
+        assert_eq!(tracker.update(0, 100.), None);
+        assert_eq!(tracker.update(1, 0.), None);
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::{Error, anyhow};
+use std::{ops::Mul, str::FromStr};
+
+/// normalized coordinate, from top left
+pub type Point = (f32, f32);
+
+/// libinput calibration matrix, the last row is omitted
+///
+/// matrix is look like this
+///
+/// ```text
+/// [0] [1] [2]
+/// [3] [4] [5]
+///  0   0   1
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix(pub [f32; 6]);
+
+impl Matrix {
+    pub const IDENTITY: Self = Self([1., 0., 0., 0., 1., 0.]);
+    pub const ROTATE_90: Self = Self([0., -1., 1., 1., 0., 0.]);
+    pub const ROTATE_180: Self = Self([-1., 0., 1., 0., -1., 1.]);
+    pub const ROTATE_270: Self = Self([0., 1., 0., -1., 0., 1.]);
+    pub const FLIPPED: Self = Self([-1., 0., 1., 0., 1., 0.]);
+    pub const FLIPPED_90: Self = Self([0., -1., 1., -1., 0., 1.]);
+    pub const FLIPPED_180: Self = Self([1., 0., 0., 0., -1., 1.]);
+    pub const FLIPPED_270: Self = Self([0., 1., 0., 1., 0., 0.]);
+
+    /// transform a normalized point
+    pub fn apply(&self, (x, y): Point) -> Point {
+        let m = &self.0;
+        (m[0] * x + m[1] * y + m[2], m[3] * x + m[4] * y + m[5])
+    }
+
+    /// `None` if the matrix is not invertible
+    pub fn inverse(&self) -> Option<Self> {
+        let m = &self.0;
+        let det = m[0] * m[4] - m[1] * m[3];
+        if det == 0. {
+            return None;
+        }
+        let a = m[4] / det;
+        let b = -m[1] / det;
+        let d = -m[3] / det;
+        let e = m[0] / det;
+        Some(Self([
+            a,
+            b,
+            -(a * m[2] + b * m[5]),
+            d,
+            e,
+            -(d * m[2] + e * m[5]),
+        ]))
+    }
+}
+
+impl Default for Matrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// `a * b` apply `b` first, then `a`
+impl Mul for Matrix {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (&self.0, &rhs.0);
+        Self([
+            a[0] * b[0] + a[1] * b[3],
+            a[0] * b[1] + a[1] * b[4],
+            a[0] * b[2] + a[1] * b[5] + a[2],
+            a[3] * b[0] + a[4] * b[3],
+            a[3] * b[1] + a[4] * b[4],
+            a[3] * b[2] + a[4] * b[5] + a[5],
+        ])
+    }
+}
+
+/// least squares fit the matrix which map `(raw, target)` samples,
+/// `None` if there are not enough non-collinear samples
+pub fn fit_matrix(samples: &[(Point, Point)]) -> Option<Matrix> {
+    let mut normal = [[0f64; 3]; 3];
+    let mut u = [0f64; 3];
+    let mut v = [0f64; 3];
+    for &((x, y), (tx, ty)) in samples {
+        let row = [x as f64, y as f64, 1.];
+        for i in 0..3 {
+            for j in 0..3 {
+                normal[i][j] += row[i] * row[j];
+            }
+            u[i] += row[i] * tx as f64;
+            v[i] += row[i] * ty as f64;
+        }
+    }
+    let [a, b, c] = solve3(normal, u)?;
+    let [d, e, f] = solve3(normal, v)?;
+    Some(Matrix([a, b, c, d, e, f].map(|x| x as f32)))
+}
+
+/// solve `m * x = y` with cramer's rule
+fn solve3(m: [[f64; 3]; 3], y: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let base = det(m);
+    if base.abs() < f64::EPSILON {
+        return None;
+    }
+    let mut x = [0.; 3];
+    for (col, x) in x.iter_mut().enumerate() {
+        let mut m = m;
+        for row in 0..3 {
+            m[row][col] = y[row];
+        }
+        *x = det(m) / base;
+    }
+    Some(x)
+}
+
+/// all eight output transforms, same as wayland `wl_output.transform`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationMode {
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl RotationMode {
+    pub const ALL: [Self; 8] = [
+        Self::Normal,
+        Self::Rotate90,
+        Self::Rotate180,
+        Self::Rotate270,
+        Self::Flipped,
+        Self::Flipped90,
+        Self::Flipped180,
+        Self::Flipped270,
+    ];
+
+    pub fn matrix(self) -> Matrix {
+        match self {
+            Self::Normal => Matrix::IDENTITY,
+            Self::Rotate90 => Matrix::ROTATE_90,
+            Self::Rotate180 => Matrix::ROTATE_180,
+            Self::Rotate270 => Matrix::ROTATE_270,
+            Self::Flipped => Matrix::FLIPPED,
+            Self::Flipped90 => Matrix::FLIPPED_90,
+            Self::Flipped180 => Matrix::FLIPPED_180,
+            Self::Flipped270 => Matrix::FLIPPED_270,
+        }
+    }
+
+    fn from_matrix(matrix: Matrix) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.matrix() == matrix)
+            .expect("transforms should be closed under composition")
+    }
+
+    /// apply `other` first, then `self`
+    pub fn compose(self, other: Self) -> Self {
+        Self::from_matrix(self.matrix() * other.matrix())
+    }
+
+    pub fn inverse(self) -> Self {
+        Self::from_matrix(self.matrix().inverse().unwrap())
+    }
+
+    /// `offset` turns the raw touch into the normal orientation, so it is applied first
+    pub fn offset_by(self, offset: Self) -> Self {
+        self.compose(offset)
+    }
+}
+
+impl FromStr for RotationMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s {
+            "normal" => Self::Normal,
+            "rotate90" => Self::Rotate90,
+            "rotate180" => Self::Rotate180,
+            "rotate270" => Self::Rotate270,
+            "flipped" => Self::Flipped,
+            "flipped90" | "flipped-90" => Self::Flipped90,
+            "flipped180" | "flipped-180" => Self::Flipped180,
+            "flipped270" | "flipped-270" => Self::Flipped270,
+            s => return Err(anyhow!("Uknown value for rotate calibration: {s}")),
+        };
+        Ok(mode)
+    }
+}
+
+/// touchscreen area inside the whole output layout, in pixel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub layout_width: f32,
+    pub layout_height: f32,
+}
+
+impl Region {
+    /// map normalized touchscreen coordinate into the region of layout
+    pub fn matrix(&self) -> Matrix {
+        Matrix([
+            self.width / self.layout_width,
+            0.,
+            self.x / self.layout_width,
+            0.,
+            self.height / self.layout_height,
+            self.y / self.layout_height,
+        ])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    pub rotation: RotationMode,
+    /// use `orientation` instead of `rotation`
+    pub follow: bool,
+    /// rotation of the last `on-rotate-*` action
+    pub orientation: RotationMode,
+    pub region: Option<Region>,
+}
+
+impl Calibration {
+    pub fn current_rotation(&self) -> RotationMode {
+        match self.follow {
+            true => self.orientation,
+            false => self.rotation,
+        }
+    }
+
+    /// `normal` is the device default calibration
+    pub fn matrix(&self, normal: Matrix) -> Matrix {
+        let matrix = normal * self.current_rotation().matrix();
+        match self.region {
+            Some(region) => region.matrix() * matrix,
+            None => matrix,
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            rotation: RotationMode::Normal,
+            follow: false,
+            orientation: RotationMode::Normal,
+            region: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORNERS: [Point; 4] = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)];
+
+    #[test]
+    fn rotate_matrix() {
+        assert_eq!(Matrix::IDENTITY * Matrix::IDENTITY, Matrix::IDENTITY);
+        assert_eq!(Matrix::IDENTITY * Matrix::ROTATE_90, Matrix::ROTATE_90);
+        assert_eq!(Matrix::IDENTITY * Matrix::ROTATE_180, Matrix::ROTATE_180);
+        assert_eq!(Matrix::IDENTITY * Matrix::ROTATE_270, Matrix::ROTATE_270);
+    }
+
+    #[test]
+    fn transform_corners() {
+        for mode in RotationMode::ALL {
+            let mut mapped = CORNERS.map(|p| mode.matrix().apply(p));
+            mapped.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(mapped, [(0., 0.), (0., 1.), (1., 0.), (1., 1.)], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn transform_compose() {
+        for a in RotationMode::ALL {
+            for b in RotationMode::ALL {
+                let ab = a.compose(b);
+                for p in CORNERS {
+                    let expect = a.matrix().apply(b.matrix().apply(p));
+                    assert_eq!(ab.matrix().apply(p), expect, "{a:?} * {b:?}");
+                }
+            }
+        }
+        assert_eq!(
+            RotationMode::Rotate90.compose(RotationMode::Flipped),
+            RotationMode::Flipped90
+        );
+        assert_eq!(
+            RotationMode::Rotate90.compose(RotationMode::Rotate270),
+            RotationMode::Normal
+        );
+    }
+
+    #[test]
+    fn transform_inverse() {
+        for mode in RotationMode::ALL {
+            assert_eq!(mode.compose(mode.inverse()), RotationMode::Normal);
+            assert_eq!(mode.inverse().compose(mode), RotationMode::Normal);
+            for p in CORNERS {
+                assert_eq!(mode.inverse().matrix().apply(mode.matrix().apply(p)), p);
+            }
+        }
+        let matrix = Matrix([2., 0., 0.5, 0., 4., -1.]);
+        assert_eq!(matrix * matrix.inverse().unwrap(), Matrix::IDENTITY);
+        assert_eq!(Matrix([1., 2., 0., 2., 4., 0.]).inverse(), None);
+    }
+
+    #[test]
+    fn follow_offset() {
+        // panel mirrored horizontally, device rotated left up
+        let left_up = RotationMode::Rotate90.offset_by(RotationMode::Flipped);
+        assert_eq!(left_up, RotationMode::Flipped90);
+        let calibration = Calibration {
+            follow: true,
+            orientation: left_up,
+            ..Default::default()
+        };
+        let raw = (0.2, 0.1);
+        assert_eq!(
+            calibration.matrix(Matrix::IDENTITY).apply(raw),
+            Matrix::ROTATE_90.apply(Matrix::FLIPPED.apply(raw))
+        );
+        assert_eq!(
+            RotationMode::Rotate270.offset_by(RotationMode::Flipped),
+            RotationMode::Flipped270
+        );
+        assert_eq!(
+            RotationMode::Rotate270.offset_by(RotationMode::Rotate90),
+            RotationMode::Normal
+        );
+    }
+
+    #[test]
+    fn fit() {
+        let expect = Matrix([0.9, 0.05, 0.02, -0.03, 1.1, -0.04]);
+        let samples = [(0.1, 0.1), (0.9, 0.1), (0.9, 0.9), (0.1, 0.9), (0.5, 0.5)]
+            .map(|target| (expect.inverse().unwrap().apply(target), target));
+        let matrix = fit_matrix(&samples).unwrap();
+        for (a, b) in matrix.0.into_iter().zip(expect.0) {
+            assert!((a - b).abs() < 1e-4, "{matrix:?}");
+        }
+        assert_eq!(fit_matrix(&samples[..2]), None);
+    }
+
+    #[test]
+    fn region_matrix() {
+        let region = Region {
+            x: 1920.,
+            y: 0.,
+            width: 1920.,
+            height: 1080.,
+            layout_width: 3840.,
+            layout_height: 1080.,
+        };
+        assert_eq!(region.matrix(), Matrix([0.5, 0., 0.5, 0., 1., 0.]));
+        let calibration = Calibration {
+            rotation: RotationMode::Rotate180,
+            region: Some(region),
+            ..Default::default()
+        };
+        assert_eq!(
+            calibration.matrix(Matrix::IDENTITY),
+            Matrix([-0.5, 0., 1., 0., -1., 1.])
+        );
+    }
+}
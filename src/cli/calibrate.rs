@@ -0,0 +1,65 @@
+use crate::{
+    calibration::{Matrix, Point, RotationMode, fit_matrix},
+    libinput::EventListener,
+};
+use anyhow::{Context, Result, anyhow};
+use futures::StreamExt;
+use input::{
+    Event,
+    event::{TouchEvent, touch::TouchEventPosition},
+};
+
+const TARGETS: [Point; 5] = [(0.1, 0.1), (0.9, 0.1), (0.9, 0.9), (0.1, 0.9), (0.5, 0.5)];
+
+/// `rotation` is the current transform of the output
+pub async fn calibrate(touchscreen: &str, rotation: RotationMode) -> Result<()> {
+    let mut event = EventListener::new(Default::default())?;
+    let mut device = event
+        .path_add_device(touchscreen)
+        .context("Cannot add touchscreen")?;
+    device
+        .config_calibration_set_matrix(Matrix::IDENTITY.0)
+        .map_err(|err| anyhow!("Set calibration matrix error: {:?}", err))?;
+
+    let mut samples = vec![];
+    for target in TARGETS {
+        println!(
+            "Touch the point at {:.0}% from left, {:.0}% from top, then lift",
+            target.0 * 100.,
+            target.1 * 100.
+        );
+        let raw = loop {
+            let event = event.next().await.context("Touchscreen closed")??;
+            if let Event::Touch(TouchEvent::Down(down)) = event {
+                break (down.x_transformed(1) as f32, down.y_transformed(1) as f32);
+            }
+        };
+        loop {
+            let event = event.next().await.context("Touchscreen closed")??;
+            if let Event::Touch(TouchEvent::Up(_)) = event {
+                break;
+            }
+        }
+        log::debug!("Get touch: {:?}", raw);
+        samples.push((raw, target));
+    }
+
+    let matrix = fit_matrix(&samples).context("Cannot fit the calibration, touch again")?;
+    let error = samples
+        .iter()
+        .map(|&(raw, (x, y))| {
+            let (tx, ty) = matrix.apply(raw);
+            ((tx - x).powi(2) + (ty - y).powi(2)).sqrt()
+        })
+        .fold(0f32, f32::max);
+    println!("Max error: {:.2}%", error * 100.);
+
+    // runtime apply rotation before the base calibration
+    let Matrix(base) = matrix * rotation.inverse().matrix();
+    println!("Put this in the settings block:\n");
+    println!(
+        "base-calibration {}",
+        base.map(|x| format!("{x:.6}")).join(" ")
+    );
+    Ok(())
+}
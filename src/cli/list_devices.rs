@@ -0,0 +1,116 @@
+use crate::{
+    iio::{compass::CompassProxy, sensor_proxy::SensorProxyProxy},
+    libinput::new_libinput,
+};
+use anyhow::Result;
+use input::{DeviceCapability, event::switch::Switch};
+use std::{
+    fs::{canonicalize, read_dir},
+    path::{Path, PathBuf},
+};
+use zbus::Connection;
+
+const CAPABILITIES: [(DeviceCapability, &str); 7] = [
+    (DeviceCapability::Keyboard, "keyboard"),
+    (DeviceCapability::Pointer, "pointer"),
+    (DeviceCapability::Touch, "touch"),
+    (DeviceCapability::TabletTool, "tablet-tool"),
+    (DeviceCapability::TabletPad, "tablet-pad"),
+    (DeviceCapability::Gesture, "gesture"),
+    (DeviceCapability::Switch, "switch"),
+];
+
+const SWITCHES: [(Switch, &str); 2] = [(Switch::TabletMode, "tablet-mode"), (Switch::Lid, "lid")];
+
+pub async fn list_devices() -> Result<()> {
+    let mut nodes = read_dir("/dev/input")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let number = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("event")?
+                .parse::<u32>()
+                .ok()?;
+            Some((number, path))
+        })
+        .collect::<Vec<_>>();
+    nodes.sort();
+
+    let mut input = new_libinput(Default::default());
+    for (_, node) in nodes {
+        let Some(device) = input.path_add_device(&node.to_string_lossy()) else {
+            println!("{}: cannot open", node.display());
+            continue;
+        };
+        println!("{}: {}", node.display(), device.name());
+        println!(
+            "  id: {:04x}:{:04x}",
+            device.id_vendor(),
+            device.id_product()
+        );
+
+        let capabilities = CAPABILITIES
+            .into_iter()
+            .filter(|(capability, _)| device.has_capability(*capability))
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        println!("  capabilities: {}", capabilities.join(", "));
+
+        let switches = SWITCHES
+            .into_iter()
+            .filter(|(switch, _)| device.switch_has_switch(*switch) == Ok(true))
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        if !switches.is_empty() {
+            println!("  switches: {}", switches.join(", "));
+        }
+
+        for selector in stable_selectors(&node) {
+            println!("  selector: {}", selector.display());
+        }
+        input.path_remove_device(device);
+    }
+
+    println!();
+    if let Err(err) = list_sensors().await {
+        println!("iio-sensor-proxy: not available ({err})");
+    }
+    Ok(())
+}
+
+/// symlinks in `/dev/input/by-id` and `/dev/input/by-path` point to `node`
+fn stable_selectors(node: &Path) -> Vec<PathBuf> {
+    let Ok(node) = canonicalize(node) else {
+        return vec![];
+    };
+    ["/dev/input/by-id", "/dev/input/by-path"]
+        .into_iter()
+        .filter_map(|dir| read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| canonicalize(path).is_ok_and(|path| path == node))
+        .collect()
+}
+
+async fn list_sensors() -> Result<()> {
+    let conn = Connection::system().await?;
+    let proxy = SensorProxyProxy::new(&conn).await?;
+    let compass = CompassProxy::builder(&conn)
+        .destination("net.hadess.SensorProxy")?
+        .path("/net/hadess/SensorProxy/Compass")?
+        .build()
+        .await?;
+    let yes_no = |has: bool| if has { "yes" } else { "no" };
+    println!("iio-sensor-proxy:");
+    println!(
+        "  accelerometer: {}",
+        yes_no(proxy.has_accelerometer().await?)
+    );
+    println!("  light: {}", yes_no(proxy.has_ambient_light().await?));
+    println!("  proximity: {}", yes_no(proxy.has_proximity().await?));
+    println!("  compass: {}", yes_no(compass.has_compass().await?));
+    Ok(())
+}
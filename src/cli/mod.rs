@@ -0,0 +1,2 @@
+pub mod calibrate;
+pub mod list_devices;
@@ -10,6 +10,12 @@ pub struct Config {
     pub varibles: Vec<Variables>,
     #[knus(children(name = "actions"))]
     pub actions: Vec<Actions>,
+    #[knus(children(name = "modes"))]
+    pub modes: Vec<Modes>,
+    #[knus(children(name = "bindings"))]
+    pub bindings: Vec<Bindings>,
+    #[knus(children(name = "remap"))]
+    pub remap: Vec<Remap>,
 }
 
 #[derive(Debug, Decode)]
@@ -17,9 +23,55 @@ pub struct Settings {
     #[knus(child, unwrap(argument))]
     pub default_mode: SettingMode,
     #[knus(child, unwrap(argument))]
-    pub switch: String,
+    pub switch: Option<String>,
+    #[knus(child, unwrap(argument))]
+    pub lid: Option<String>,
     #[knus(child, unwrap(argument))]
     pub touchscreen: Option<String>,
+    #[knus(child, unwrap(argument))]
+    pub pen: Option<String>,
+    /// disable touchscreen while pen is in proximity
+    #[knus(child, unwrap(argument))]
+    pub palm_rejection: Option<bool>,
+    #[knus(child, unwrap(arguments))]
+    pub base_calibration: Option<Vec<f32>>,
+    #[knus(child, unwrap(argument))]
+    pub calibration_offset: Option<String>,
+    #[knus(child, unwrap(argument))]
+    pub accelerometer: Option<AccelerometerSource>,
+    /// in degree
+    #[knus(child, unwrap(argument))]
+    pub accelerometer_threshold: Option<u32>,
+    /// in millisecond
+    #[knus(child, unwrap(argument))]
+    pub accelerometer_interval: Option<u64>,
+    /// sensor orientation to the real orientation
+    #[knus(child)]
+    pub orientation_map: Option<OrientationMap>,
+    /// other orientations are ignored
+    #[knus(child, unwrap(arguments))]
+    pub allowed_orientation: Option<Vec<String>>,
+    #[knus(child, unwrap(argument))]
+    pub hinge: Option<HingeSource>,
+    #[knus(child, unwrap(arguments))]
+    pub virtual_switch: Option<Vec<VirtualSwitch>>,
+    /// keyboard to grab for `remap`
+    #[knus(child, unwrap(argument))]
+    pub remap_keyboard: Option<String>,
+    /// user name, uid or `active-session` to run commands as
+    #[knus(child, unwrap(argument))]
+    pub run_as: Option<String>,
+    /// default interpreter of `script` and its arguments
+    #[knus(child, unwrap(arguments))]
+    pub script_interpreter: Option<Vec<String>>,
+    /// modes which the virtual tablet mode switch is on
+    #[knus(child, unwrap(arguments))]
+    pub tablet_modes: Option<Vec<String>>,
+    /// in millisecond
+    #[knus(child, unwrap(argument))]
+    pub hinge_interval: Option<u64>,
+    #[knus(child, unwrap(argument))]
+    pub device_access: Option<DeviceAccess>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
@@ -28,6 +80,75 @@ pub enum SettingMode {
     Tablet,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum AccelerometerSource {
+    SensorProxy,
+    Iio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum HingeSource {
+    Auto,
+    DualAccel,
+    Sensor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum DeviceAccess {
+    Direct,
+    /// take device from the logind session, no root required
+    Logind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum VirtualSwitch {
+    TabletMode,
+    Lid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum LidState {
+    Open,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DecodeScalar)]
+pub enum KeyboardState {
+    Present,
+    Absent,
+}
+
+#[derive(Debug, Decode)]
+pub struct OrientationMap {
+    #[knus(children)]
+    pub entries: Vec<OrientationMapEntry>,
+}
+
+#[derive(Debug, Decode)]
+pub struct OrientationMapEntry {
+    #[knus(node_name)]
+    pub from: String,
+    #[knus(argument)]
+    pub to: String,
+}
+
+/// key remap table of a mode
+#[derive(Debug, Decode)]
+pub struct Remap {
+    #[knus(property)]
+    pub mode: String,
+    #[knus(children)]
+    pub entries: Vec<RemapEntry>,
+}
+
+#[derive(Debug, Decode)]
+pub struct RemapEntry {
+    #[knus(node_name)]
+    pub from: String,
+    #[knus(argument)]
+    pub to: String,
+}
+
 #[derive(Debug, Decode)]
 pub struct Variables {
     #[knus(children)]
@@ -52,19 +173,159 @@ pub struct Actions {
 pub struct Action {
     #[knus(node_name)]
     pub event: String,
+    /// e.g. the angle of `on-hinge-above`
+    #[knus(argument)]
+    pub threshold: Option<u32>,
     #[knus(children)]
     pub tasks: Vec<Task>,
 }
 
+#[derive(Debug, Decode)]
+pub struct Modes {
+    #[knus(children)]
+    pub modes: Vec<Mode>,
+}
+
+#[derive(Debug, Decode)]
+pub struct Mode {
+    #[knus(node_name)]
+    pub name: String,
+    #[knus(children)]
+    pub rules: Vec<ModeRule>,
+}
+
+/// all rules should match to enter the mode
+#[derive(Debug, Decode)]
+pub enum ModeRule {
+    Switch(#[knus(argument)] SettingMode),
+
+    Lid(#[knus(argument)] LidState),
+
+    /// match any of the orientations
+    Orientation(#[knus(arguments)] Vec<String>),
+
+    /// external keyboard
+    Keyboard(#[knus(argument)] KeyboardState),
+
+    /// match any of the tilts
+    Tilt(#[knus(arguments)] Vec<String>),
+
+    HingeAbove(#[knus(argument)] u32),
+
+    HingeBelow(#[knus(argument)] u32),
+}
+
+#[derive(Debug, Decode)]
+pub struct Bindings {
+    #[knus(children)]
+    pub bindings: Vec<Binding>,
+}
+
+/// only active in `mode` if it is set
+#[derive(Debug, Decode)]
+pub enum Binding {
+    /// key name or code
+    Key(
+        #[knus(argument)] String,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+
+    PadButton(
+        #[knus(argument)] u32,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+
+    /// ring number and `cw`/`ccw`
+    PadRing(
+        #[knus(argument)] u32,
+        #[knus(argument)] String,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+
+    /// `up`/`down`/`left`/`right`
+    Swipe(
+        #[knus(argument)] String,
+        #[knus(property(name = "fingers"))] Option<u32>,
+        #[knus(property(name = "device"))] Option<String>,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+
+    /// `in`/`out`
+    Pinch(
+        #[knus(argument)] String,
+        #[knus(property(name = "fingers"))] Option<u32>,
+        #[knus(property(name = "device"))] Option<String>,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+
+    Hold(
+        #[knus(property(name = "fingers"))] Option<u32>,
+        #[knus(property(name = "device"))] Option<String>,
+        #[knus(property(name = "mode"))] Option<String>,
+        #[knus(children)] Vec<Task>,
+    ),
+}
+
 #[derive(Debug, Decode)]
 pub enum Task {
     Action(#[knus(argument, str)] VStr),
 
-    Cmd(#[knus(arguments, str)] Vec<VStr>),
+    Cmd(
+        #[knus(arguments, str)] Vec<VStr>,
+        #[knus(property(name = "cwd"), str)] Option<VStr>,
+        /// in millisecond, killed on expiry
+        #[knus(property(name = "timeout"), str)]
+        Option<VStr>,
+        /// don't wait for exit
+        #[knus(property(name = "detach"))]
+        Option<bool>,
+        #[knus(property(name = "stdin"), str)] Option<VStr>,
+        #[knus(children(name = "env"))] Vec<Env>,
+    ),
+
+    /// the script is the last argument of the interpreter, variables are passed as `ROTA_*` environment variables
+    Script(
+        #[knus(argument)] String,
+        #[knus(property(name = "cwd"), str)] Option<VStr>,
+        #[knus(property(name = "timeout"), str)] Option<VStr>,
+        #[knus(property(name = "detach"))] Option<bool>,
+        #[knus(children(name = "interpreter"))] Vec<Interpreter>,
+    ),
+
+    /// the only child should be `cmd`
+    Capture(#[knus(argument)] String, #[knus(children)] Vec<Task>),
 
     Rotation(#[knus(argument, str)] VStr),
 
     RotateCalibration(#[knus(argument, str)] VStr),
+
+    MapToOutput(#[knus(arguments, str)] Vec<VStr>),
+
+    Touchscreen(#[knus(argument, str)] VStr),
+
+    /// grace period in millisecond or `disable`
+    DeferRotation(#[knus(argument, str)] VStr),
+}
+
+/// override `script-interpreter` of `script`
+#[derive(Debug, Decode)]
+pub struct Interpreter {
+    #[knus(arguments, str)]
+    pub args: Vec<VStr>,
+}
+
+/// environment variable of `cmd`
+#[derive(Debug, Decode)]
+pub struct Env {
+    #[knus(argument)]
+    pub name: String,
+    #[knus(argument, str)]
+    pub value: VStr,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,256 @@
+use anyhow::{Error, anyhow};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// in touchpad logical pixel
+const TOUCHPAD_SWIPE_DISTANCE: f64 = 300.;
+
+/// normalized to the screen size
+const TOUCHSCREEN_SWIPE_DISTANCE: f64 = 0.1;
+
+/// scale of the finger spread, or the inverse for pinch in
+const PINCH_SCALE: f64 = 1.3;
+
+const HOLD_USEC: u64 = 500_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// the dominant axis, `y` is downward
+    fn from_delta((dx, dy): (f64, f64)) -> Self {
+        match (dx.abs() > dy.abs(), dx > 0., dy > 0.) {
+            (true, true, _) => Self::Right,
+            (true, false, _) => Self::Left,
+            (false, _, true) => Self::Down,
+            (false, _, false) => Self::Up,
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let direction = match s {
+            "up" => Self::Up,
+            "down" => Self::Down,
+            "left" => Self::Left,
+            "right" => Self::Right,
+            s => return Err(anyhow!("Unknown value for swipe direction: {s}")),
+        };
+        Ok(direction)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Swipe(Direction),
+    PinchIn,
+    PinchOut,
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GestureDevice {
+    Touchpad,
+    Touchscreen,
+}
+
+impl FromStr for GestureDevice {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let device = match s {
+            "touchpad" => Self::Touchpad,
+            "touchscreen" => Self::Touchscreen,
+            s => return Err(anyhow!("Unknown value for gesture device: {s}")),
+        };
+        Ok(device)
+    }
+}
+
+/// accumulate libinput touchpad gesture updates
+#[derive(Debug, Default)]
+pub struct TouchpadTracker {
+    delta: (f64, f64),
+    scale: f64,
+}
+
+impl TouchpadTracker {
+    pub fn begin(&mut self) {
+        self.delta = (0., 0.);
+        self.scale = 1.;
+    }
+
+    pub fn swipe_update(&mut self, dx: f64, dy: f64) {
+        self.delta.0 += dx;
+        self.delta.1 += dy;
+    }
+
+    pub fn pinch_update(&mut self, scale: f64) {
+        self.scale = scale;
+    }
+
+    pub fn swipe_end(&self, cancelled: bool) -> Option<Gesture> {
+        let (dx, dy) = self.delta;
+        if cancelled || dx.hypot(dy) < TOUCHPAD_SWIPE_DISTANCE {
+            return None;
+        }
+        Some(Gesture::Swipe(Direction::from_delta(self.delta)))
+    }
+
+    pub fn pinch_end(&self, cancelled: bool) -> Option<Gesture> {
+        match self.scale {
+            _ if cancelled => None,
+            scale if scale > PINCH_SCALE => Some(Gesture::PinchOut),
+            scale if scale < 1. / PINCH_SCALE => Some(Gesture::PinchIn),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Touch {
+    start: (f64, f64),
+    current: (f64, f64),
+    down: bool,
+}
+
+/// recognize gesture from raw touchscreen events, in normalized screen coordinate
+///
+/// the gesture is decided when the last finger is up
+#[derive(Debug, Default)]
+pub struct TouchRecognizer {
+    touches: BTreeMap<u32, Touch>,
+    start: u64,
+}
+
+impl TouchRecognizer {
+    pub fn down(&mut self, slot: u32, position: (f64, f64), time_usec: u64) {
+        if self.touches.is_empty() {
+            self.start = time_usec;
+        }
+        self.touches.insert(
+            slot,
+            Touch {
+                start: position,
+                current: position,
+                down: true,
+            },
+        );
+    }
+
+    pub fn motion(&mut self, slot: u32, position: (f64, f64)) {
+        if let Some(touch) = self.touches.get_mut(&slot) {
+            touch.current = position;
+        }
+    }
+
+    /// `Some((gesture, fingers))` if the last finger is up
+    pub fn up(&mut self, slot: u32, time_usec: u64) -> Option<(Gesture, u32)> {
+        self.touches.get_mut(&slot)?.down = false;
+        if self.touches.values().any(|touch| touch.down) {
+            return None;
+        }
+        let touches = std::mem::take(&mut self.touches);
+        let fingers = touches.len() as u32;
+        let center = |point: fn(&Touch) -> (f64, f64)| {
+            let (x, y) = touches
+                .values()
+                .map(point)
+                .fold((0., 0.), |(x, y), (px, py)| (x + px, y + py));
+            (x / fingers as f64, y / fingers as f64)
+        };
+        let spread = |point: fn(&Touch) -> (f64, f64)| {
+            let (cx, cy) = center(point);
+            touches
+                .values()
+                .map(|touch| {
+                    let (x, y) = point(touch);
+                    (x - cx).hypot(y - cy)
+                })
+                .sum::<f64>()
+        };
+        let (sx, sy) = center(|touch| touch.start);
+        let (ex, ey) = center(|touch| touch.current);
+        let delta = (ex - sx, ey - sy);
+
+        let scale = spread(|touch| touch.current) / spread(|touch| touch.start);
+        let gesture = if fingers >= 2 && scale > PINCH_SCALE {
+            Gesture::PinchOut
+        } else if fingers >= 2 && scale < 1. / PINCH_SCALE {
+            Gesture::PinchIn
+        } else if delta.0.hypot(delta.1) >= TOUCHSCREEN_SWIPE_DISTANCE {
+            Gesture::Swipe(Direction::from_delta(delta))
+        } else if time_usec.saturating_sub(self.start) >= HOLD_USEC {
+            Gesture::Hold
+        } else {
+            return None;
+        };
+        Some((gesture, fingers))
+    }
+
+    pub fn cancel(&mut self) {
+        self.touches.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_recognize() {
+        let mut recognizer = TouchRecognizer::default();
+
+        // three finger swipe up
+        for slot in 0..3 {
+            recognizer.down(slot, (0.4 + slot as f64 * 0.1, 0.8), 0);
+        }
+        for slot in 0..3 {
+            recognizer.motion(slot, (0.4 + slot as f64 * 0.1, 0.5));
+        }
+        assert_eq!(recognizer.up(0, 100_000), None);
+        assert_eq!(recognizer.up(1, 100_000), None);
+        assert_eq!(
+            recognizer.up(2, 100_000),
+            Some((Gesture::Swipe(Direction::Up), 3))
+        );
+
+        // two finger pinch out
+        recognizer.down(0, (0.45, 0.5), 0);
+        recognizer.down(1, (0.55, 0.5), 0);
+        recognizer.motion(0, (0.3, 0.5));
+        recognizer.motion(1, (0.7, 0.5));
+        recognizer.up(1, 100_000);
+        assert_eq!(recognizer.up(0, 100_000), Some((Gesture::PinchOut, 2)));
+
+        // tap and hold
+        recognizer.down(0, (0.5, 0.5), 0);
+        assert_eq!(recognizer.up(0, 100_000), None);
+        recognizer.down(0, (0.5, 0.5), 0);
+        assert_eq!(recognizer.up(0, 600_000), Some((Gesture::Hold, 1)));
+    }
+
+    #[test]
+    fn touchpad_track() {
+        let mut tracker = TouchpadTracker::default();
+        tracker.begin();
+        tracker.swipe_update(-200., 10.);
+        tracker.swipe_update(-200., 10.);
+        assert_eq!(
+            tracker.swipe_end(false),
+            Some(Gesture::Swipe(Direction::Left))
+        );
+        assert_eq!(tracker.swipe_end(true), None);
+
+        tracker.begin();
+        tracker.pinch_update(0.5);
+        assert_eq!(tracker.pinch_end(false), Some(Gesture::PinchIn));
+    }
+}
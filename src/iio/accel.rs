@@ -0,0 +1,185 @@
+use super::{sensor_proxy::AccelerometerOrientation, sysfs::IioDevice};
+use futures::Stream;
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+use tokio::time::{Interval, MissedTickBehavior, interval};
+
+/// tilt in degree, same as iio-sensor-proxy
+pub const DEFAULT_THRESHOLD: u32 = 35;
+
+/// in degree, hysteresis of leaving portrait
+const SAME_AXIS_LIMIT: f64 = 5.;
+
+/// read the accelerometer from sysfs directly, without iio-sensor-proxy
+///
+/// only yield when the orientation changed, pending while it is released
+#[derive(Debug)]
+pub struct IioAccelerometer {
+    device: IioDevice,
+    period: Duration,
+    threshold: f64,
+    interval: Option<Interval>,
+    orientation: AccelerometerOrientation,
+}
+
+impl IioAccelerometer {
+    pub fn new(device: IioDevice, period: Duration, threshold: u32) -> Self {
+        Self {
+            device,
+            period,
+            threshold: threshold as f64,
+            interval: None,
+            orientation: AccelerometerOrientation::Undefined,
+        }
+    }
+
+    /// the display accelerometer, `accel-base` is skipped
+    pub fn find(root: &Path) -> io::Result<Option<IioDevice>> {
+        let devices = IioDevice::list(root)?
+            .into_iter()
+            .filter(|device| device.has_attr("in_accel_x_raw"))
+            .collect::<Vec<_>>();
+        let label = |device: &IioDevice| device.label().unwrap_or_default();
+        let device = devices
+            .iter()
+            .find(|device| ["accel-display", "accel_display"].contains(&label(device).as_str()))
+            .or_else(|| {
+                devices
+                    .iter()
+                    .find(|device| !["accel-base", "accel_base"].contains(&label(device).as_str()))
+            })
+            .cloned();
+        Ok(device)
+    }
+
+    /// start polling, the first reading is always yielded
+    pub fn claim(&mut self) {
+        let mut interval = interval(self.period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        self.interval = Some(interval);
+        self.orientation = AccelerometerOrientation::Undefined;
+    }
+
+    pub fn release(&mut self) {
+        self.interval = None;
+    }
+}
+
+impl Stream for IioAccelerometer {
+    type Item = io::Result<AccelerometerOrientation>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let Some(interval) = &mut self.interval else {
+                return Poll::Pending;
+            };
+            ready!(interval.poll_tick(cx));
+            let accel = match self.device.read_accel() {
+                Ok(accel) => accel,
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            };
+            let orientation = orientation(accel, &self.orientation, self.threshold);
+            if orientation != self.orientation {
+                self.orientation = orientation.clone();
+                return Poll::Ready(Some(Ok(orientation)));
+            }
+        }
+    }
+}
+
+/// similar to iio-sensor-proxy, keep `prev` if the tilt is below `threshold`
+pub fn orientation(
+    [x, y, z]: [f64; 3],
+    prev: &AccelerometerOrientation,
+    threshold: f64,
+) -> AccelerometerOrientation {
+    let portrait = x.atan2(y.hypot(z)).to_degrees();
+    if portrait.abs() > threshold {
+        return match portrait < 0. {
+            true => AccelerometerOrientation::LeftUp,
+            false => AccelerometerOrientation::RightUp,
+        };
+    }
+    if matches!(
+        prev,
+        AccelerometerOrientation::LeftUp | AccelerometerOrientation::RightUp
+    ) && portrait.abs() > threshold - SAME_AXIS_LIMIT
+    {
+        return prev.clone();
+    }
+    let landscape = y.atan2(x.hypot(z)).to_degrees();
+    if landscape.abs() > threshold {
+        return match landscape < 0. {
+            true => AccelerometerOrientation::BottomUp,
+            false => AccelerometerOrientation::Normal,
+        };
+    }
+    prev.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    #[test]
+    fn orientation_threshold() {
+        use AccelerometerOrientation::*;
+
+        let g = 9.8;
+        let threshold = DEFAULT_THRESHOLD as f64;
+        assert_eq!(orientation([0., g, 0.], &Undefined, threshold), Normal);
+        assert_eq!(orientation([0., -g, 0.], &Undefined, threshold), BottomUp);
+        assert_eq!(orientation([-g, 0., 0.], &Undefined, threshold), LeftUp);
+        assert_eq!(orientation([g, 0., 0.], &Undefined, threshold), RightUp);
+        // lying flat
+        assert_eq!(orientation([0., 0., g], &Undefined, threshold), Undefined);
+        assert_eq!(orientation([0., 0., g], &Normal, threshold), Normal);
+        // 45 degree between portrait and landscape
+        assert_eq!(orientation([g, g, 0.], &Normal, threshold), RightUp);
+        assert_eq!(orientation([g, g * 1.5, 0.], &RightUp, threshold), RightUp);
+        assert_eq!(orientation([g, g * 1.5, 0.], &Normal, threshold), Normal);
+    }
+
+    #[test]
+    fn fake_sysfs() {
+        let root = std::env::temp_dir().join(format!("rota-iio-{}", std::process::id()));
+        let fake = |name: &str, attrs: &[(&str, &str)]| {
+            let path = root.join(name);
+            create_dir_all(&path).unwrap();
+            for (attr, value) in attrs {
+                write(path.join(attr), format!("{value}\n")).unwrap();
+            }
+        };
+        fake("iio:device0", &[("name", "als")]);
+        fake(
+            "iio:device1",
+            &[("label", "accel-base"), ("in_accel_x_raw", "0")],
+        );
+        fake(
+            "iio:device2",
+            &[
+                ("label", "accel-display"),
+                ("in_accel_x_raw", "0"),
+                ("in_accel_y_raw", "-1000"),
+                ("in_accel_z_raw", "0"),
+                ("in_accel_scale", "0.0098"),
+                ("in_accel_mount_matrix", "-1, 0, 0; 0, -1, 0; 0, 0, 1"),
+            ],
+        );
+
+        let device = IioAccelerometer::find(&root).unwrap().unwrap();
+        let accel = device.read_accel().unwrap();
+        assert!((accel[1] - 9.8).abs() < 1e-6, "{accel:?}");
+        assert_eq!(
+            orientation(accel, &AccelerometerOrientation::Undefined, 35.),
+            AccelerometerOrientation::Normal
+        );
+        remove_dir_all(&root).unwrap();
+    }
+}
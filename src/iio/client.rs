@@ -0,0 +1,172 @@
+use super::sensor_proxy::{AccelerometerOrientation, AccelerometerTilt, SensorProxyProxy};
+use crate::util::next_some;
+use futures::StreamExt;
+use std::future::pending;
+use tokio::select;
+use zbus::{
+    Connection, Result,
+    fdo::{DBusProxy, NameOwnerChangedStream},
+    names::BusName,
+    proxy::PropertyStream,
+};
+
+const SERVICE: &str = "net.hadess.SensorProxy";
+
+#[derive(Debug)]
+pub enum SensorEvent {
+    /// the service is (re)started, all claims are restored
+    Connected,
+    Disconnected,
+    Orientation(AccelerometerOrientation),
+    Tilt(AccelerometerTilt),
+}
+
+/// iio-sensor-proxy client which survives the service restart
+///
+/// there are no sensors while the service is absent
+pub struct SensorClient {
+    conn: Connection,
+    owner_changed: NameOwnerChangedStream<'static>,
+    proxy: Option<SensorProxyProxy<'static>>,
+    orientation: Option<PropertyStream<'static, AccelerometerOrientation>>,
+    tilt: Option<PropertyStream<'static, AccelerometerTilt>>,
+    /// should claim accelerometer after reconnect
+    accelerometer: bool,
+    /// the service appeared, but not connected yet
+    reconnect: bool,
+}
+
+impl SensorClient {
+    pub async fn new(conn: Connection) -> Result<Self> {
+        let dbus = DBusProxy::new(&conn).await?;
+        let owner_changed = dbus
+            .receive_name_owner_changed_with_args(&[(0, SERVICE)])
+            .await?;
+        let reconnect = dbus.name_has_owner(BusName::try_from(SERVICE)?).await?;
+        if !reconnect {
+            log::warn!("iio-sensor-proxy is not running, wait for it");
+        }
+        Ok(Self {
+            conn,
+            owner_changed,
+            proxy: None,
+            orientation: None,
+            tilt: None,
+            accelerometer: false,
+            reconnect,
+        })
+    }
+
+    async fn connect(&mut self) -> Result<()> {
+        let proxy = SensorProxyProxy::new(&self.conn).await?;
+        let mut orientation = proxy.receive_accelerometer_orientation_changed().await;
+
+        // always have the init vaule
+        // we don't need that
+        let _ = orientation.next().await;
+
+        let tilt = match proxy.accelerometer_tilt().await {
+            Ok(_) => {
+                let mut tilt = proxy.receive_accelerometer_tilt_changed().await;
+                let _ = tilt.next().await;
+                Some(tilt)
+            }
+            Err(err) => {
+                log::info!("AccelerometerTilt is not supported: {err}");
+                None
+            }
+        };
+        if self.accelerometer {
+            proxy.claim_accelerometer().await?;
+        }
+        self.proxy = Some(proxy);
+        self.orientation = Some(orientation);
+        self.tilt = tilt;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.proxy = None;
+        self.orientation = None;
+        self.tilt = None;
+    }
+
+    /// current tilt, `None` if not connected or not supported
+    pub async fn tilt(&self) -> Option<AccelerometerTilt> {
+        self.proxy.as_ref()?.accelerometer_tilt().await.ok()
+    }
+
+    /// current orientation, `None` if not claimed or not connected
+    pub async fn orientation(&self) -> Option<AccelerometerOrientation> {
+        let proxy = self.proxy.as_ref().filter(|_| self.accelerometer)?;
+        proxy.accelerometer_orientation().await.ok()
+    }
+
+    pub async fn claim_accelerometer(&mut self) -> Result<()> {
+        self.accelerometer = true;
+        match &self.proxy {
+            Some(proxy) => proxy.claim_accelerometer().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn release_accelerometer(&mut self) -> Result<()> {
+        self.accelerometer = false;
+        match &self.proxy {
+            Some(proxy) => proxy.release_accelerometer().await,
+            None => Ok(()),
+        }
+    }
+
+    /// claim the accelerometer again and read the orientation,
+    /// `None` if not claimed or not connected
+    ///
+    /// the claim may be stale after system sleep
+    pub async fn reclaim_accelerometer(&mut self) -> Result<Option<AccelerometerOrientation>> {
+        let Some(proxy) = self.proxy.as_ref().filter(|_| self.accelerometer) else {
+            return Ok(None);
+        };
+        proxy.release_accelerometer().await?;
+        proxy.claim_accelerometer().await?;
+        proxy.accelerometer_orientation().await.map(Some)
+    }
+
+    /// cancel safe, an interrupted reconnect is retried on the next call
+    pub async fn next(&mut self) -> Result<SensorEvent> {
+        loop {
+            if self.reconnect {
+                match self.connect().await {
+                    Ok(()) => {
+                        self.reconnect = false;
+                        return Ok(SensorEvent::Connected);
+                    }
+                    Err(err) => {
+                        // the service may be gone again, wait for next owner change
+                        log::warn!("Cannot connect to iio-sensor-proxy: {err}");
+                        self.reconnect = false;
+                        self.disconnect();
+                    }
+                }
+            }
+            select! {
+                Some(signal) = self.owner_changed.next() => {
+                    let args = signal.args()?;
+                    match args.new_owner().as_ref() {
+                        Some(_) => self.reconnect = true,
+                        None => {
+                            self.disconnect();
+                            return Ok(SensorEvent::Disconnected);
+                        }
+                    }
+                }
+                Some(event) = next_some(&mut self.orientation) => {
+                    return Ok(SensorEvent::Orientation(event.get().await?));
+                }
+                Some(event) = next_some(&mut self.tilt) => {
+                    return Ok(SensorEvent::Tilt(event.get().await?));
+                }
+                else => return pending().await,
+            }
+        }
+    }
+}
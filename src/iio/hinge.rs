@@ -0,0 +1,165 @@
+use super::sysfs::IioDevice;
+use crate::config::HingeSource;
+use futures::Stream;
+use std::{
+    io,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+use tokio::time::{Interval, MissedTickBehavior, interval};
+
+/// the gravity projected on the hinge plane should be large enough,
+/// otherwise the hinge is nearly vertical and the angle is meaningless
+const MIN_PROJECTION: f64 = 0.35;
+
+/// in degree, on both side of the threshold
+const HYSTERESIS: f64 = 2.;
+
+#[derive(Debug)]
+pub enum Hinge {
+    /// two accelerometers labeled `accel-base` and `accel-display`
+    DualAccel { base: IioDevice, display: IioDevice },
+    /// hid hinge angle sensor
+    Sensor(IioDevice),
+}
+
+impl Hinge {
+    pub fn find(root: &Path, source: HingeSource) -> io::Result<Option<Self>> {
+        let devices = IioDevice::list(root)?;
+        let find_label = |labels: [&str; 2]| {
+            devices
+                .iter()
+                .find(|device| {
+                    device
+                        .label()
+                        .is_some_and(|label| labels.contains(&label.as_str()))
+                })
+                .cloned()
+        };
+        let dual = || {
+            Some(Self::DualAccel {
+                base: find_label(["accel-base", "accel_base"])?,
+                display: find_label(["accel-display", "accel_display"])?,
+            })
+        };
+        let sensor = || {
+            devices
+                .iter()
+                .find(|device| device.name().as_deref() == Some("hinge"))
+                .cloned()
+                .map(Self::Sensor)
+        };
+        let hinge = match source {
+            HingeSource::Auto => sensor().or_else(dual),
+            HingeSource::DualAccel => dual(),
+            HingeSource::Sensor => sensor(),
+        };
+        Ok(hinge)
+    }
+
+    /// `None` if the angle cannot be measured right now
+    pub fn read(&self) -> io::Result<Option<f64>> {
+        match self {
+            Self::DualAccel { base, display } => {
+                Ok(hinge_angle(base.read_accel()?, display.read_accel()?))
+            }
+            Self::Sensor(device) => device.read_scaled("in_angl0", "in_angl").map(Some),
+        }
+    }
+}
+
+/// angle between base and display in degree, `0` is closed and `180` is flat
+///
+/// assume the hinge is along x axis, and both accelerometers have the same
+/// axes when the lid is open flat
+pub fn hinge_angle(base: [f64; 3], display: [f64; 3]) -> Option<f64> {
+    let norm = |v: [f64; 3]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let (by, bz) = (base[1], base[2]);
+    let (dy, dz) = (display[1], display[2]);
+    if by.hypot(bz) < MIN_PROJECTION * norm(base) || dy.hypot(dz) < MIN_PROJECTION * norm(display) {
+        return None;
+    }
+    let angle = (by * dz - bz * dy).atan2(by * dy + bz * dz).to_degrees();
+    Some((180. + angle).rem_euclid(360.))
+}
+
+/// poll the hinge angle
+#[derive(Debug)]
+pub struct HingeListener {
+    hinge: Hinge,
+    interval: Interval,
+}
+
+impl HingeListener {
+    pub fn new(hinge: Hinge, period: Duration) -> Self {
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        Self { hinge, interval }
+    }
+}
+
+impl Stream for HingeListener {
+    type Item = io::Result<Option<f64>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        ready!(self.interval.poll_tick(cx));
+        Poll::Ready(Some(self.hinge.read()))
+    }
+}
+
+/// crossing detection for `on-hinge-above`/`on-hinge-below`
+#[derive(Debug)]
+pub struct Threshold {
+    pub angle: f64,
+    above: Option<bool>,
+}
+
+impl Threshold {
+    pub fn new(angle: f64) -> Self {
+        Self { angle, above: None }
+    }
+
+    /// `Some(above)` if it is crossed, always `Some` for the first value
+    pub fn update(&mut self, value: f64) -> Option<bool> {
+        let above = match self.above {
+            None => value > self.angle,
+            Some(true) => value >= self.angle - HYSTERESIS,
+            Some(false) => value > self.angle + HYSTERESIS,
+        };
+        if self.above == Some(above) {
+            return None;
+        }
+        self.above = Some(above);
+        Some(above)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angle() {
+        let g = 9.8;
+        let base = [0., 0., g];
+        let approx = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 1e-6;
+        assert!(approx(hinge_angle(base, [0., g, 0.]), 90.));
+        assert!(approx(hinge_angle(base, [0., 0., g]), 180.));
+        assert!(approx(hinge_angle(base, [0., -g, 0.]), 270.));
+        assert!(approx(hinge_angle(base, [0., 0., -g]), 0.));
+        // standing on the hinge side
+        assert_eq!(hinge_angle([g, 0., 0.1], [g, 0.1, 0.]), None);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut threshold = Threshold::new(190.);
+        assert_eq!(threshold.update(100.), Some(false));
+        assert_eq!(threshold.update(191.), None);
+        assert_eq!(threshold.update(195.), Some(true));
+        assert_eq!(threshold.update(189.), None);
+        assert_eq!(threshold.update(180.), Some(false));
+    }
+}
@@ -1,2 +1,6 @@
+pub mod accel;
+pub mod client;
 pub mod compass;
+pub mod hinge;
 pub mod sensor_proxy;
+pub mod sysfs;
@@ -1,3 +1,4 @@
+use std::{convert::Infallible, str::FromStr};
 use zbus::{
     proxy,
     zvariant::{self, OwnedValue, Type, Value},
@@ -32,6 +33,10 @@ trait SensorProxy {
     #[zbus(property)]
     fn accelerometer_orientation(&self) -> Result<AccelerometerOrientation>;
 
+    /// AccelerometerTilt property, not exist on older daemons
+    #[zbus(property)]
+    fn accelerometer_tilt(&self) -> Result<AccelerometerTilt>;
+
     /// HasAccelerometer property
     #[zbus(property)]
     fn has_accelerometer(&self) -> Result<bool>;
@@ -76,7 +81,15 @@ impl TryFrom<OwnedValue> for AccelerometerOrientation {
         let Value::Str(s) = &*value else {
             return Err(zvariant::Error::IncorrectType);
         };
-        let value = match s.as_str() {
+        Ok(s.parse().unwrap())
+    }
+}
+
+impl FromStr for AccelerometerOrientation {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let value = match s {
             "normal" => Self::Normal,
             "bottom-up" => Self::BottomUp,
             "left-up" => Self::LeftUp,
@@ -88,6 +101,54 @@ impl TryFrom<OwnedValue> for AccelerometerOrientation {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Type)]
+#[zvariant(signature = "s")]
+pub enum AccelerometerTilt {
+    Vertical,
+    TiltedUp,
+    TiltedDown,
+    FaceUp,
+    FaceDown,
+
+    Unknown(String),
+}
+
+impl AccelerometerTilt {
+    pub const ALL: [Self; 5] = [
+        Self::Vertical,
+        Self::TiltedUp,
+        Self::TiltedDown,
+        Self::FaceUp,
+        Self::FaceDown,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Vertical => "vertical",
+            Self::TiltedUp => "tilted-up",
+            Self::TiltedDown => "tilted-down",
+            Self::FaceUp => "face-up",
+            Self::FaceDown => "face-down",
+            Self::Unknown(s) => s,
+        }
+    }
+}
+
+impl TryFrom<OwnedValue> for AccelerometerTilt {
+    type Error = zvariant::Error;
+
+    fn try_from(value: OwnedValue) -> std::result::Result<Self, Self::Error> {
+        let Value::Str(s) = &*value else {
+            return Err(zvariant::Error::IncorrectType);
+        };
+        let value = Self::ALL
+            .into_iter()
+            .find(|tilt| tilt.as_str() == s.as_str())
+            .unwrap_or_else(|| Self::Unknown(s.as_str().into()));
+        Ok(value)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Type)]
 #[zvariant(signature = "s")]
 pub enum LightLevelUnit {
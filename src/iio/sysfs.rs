@@ -0,0 +1,108 @@
+use std::{
+    fs::{read_dir, read_to_string},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+pub const IIO_DEVICES: &str = "/sys/bus/iio/devices";
+
+/// a iio device in sysfs, e.g. `/sys/bus/iio/devices/iio:device0`
+#[derive(Debug, Clone)]
+pub struct IioDevice {
+    path: PathBuf,
+}
+
+impl IioDevice {
+    pub fn list(root: &Path) -> io::Result<Vec<Self>> {
+        let mut devices = read_dir(root)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("iio:device"))
+            })
+            .map(|path| Self { path })
+            .collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(devices)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read_string(&self, attr: &str) -> io::Result<String> {
+        Ok(read_to_string(self.path.join(attr))?.trim().to_owned())
+    }
+
+    pub fn read_f64(&self, attr: &str) -> io::Result<f64> {
+        self.read_string(attr)?
+            .parse()
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+    }
+
+    /// `name` attribute, the driver name
+    pub fn name(&self) -> Option<String> {
+        self.read_string("name").ok()
+    }
+
+    /// `label` attribute, e.g. `accel-display`
+    pub fn label(&self) -> Option<String> {
+        self.read_string("label").ok()
+    }
+
+    pub fn has_attr(&self, attr: &str) -> bool {
+        self.path.join(attr).exists()
+    }
+
+    /// raw value times scale, `{channel}_scale` is fallback to `1`
+    pub fn read_scaled(&self, channel: &str, scale: &str) -> io::Result<f64> {
+        let raw = self.read_f64(&format!("{channel}_raw"))?;
+        let scale = self
+            .read_f64(&format!("{channel}_scale"))
+            .or_else(|_| self.read_f64(&format!("{scale}_scale")))
+            .unwrap_or(1.);
+        Ok(raw * scale)
+    }
+
+    /// accelerometer vector with mount matrix applied
+    pub fn read_accel(&self) -> io::Result<[f64; 3]> {
+        let v = [
+            self.read_scaled("in_accel_x", "in_accel")?,
+            self.read_scaled("in_accel_y", "in_accel")?,
+            self.read_scaled("in_accel_z", "in_accel")?,
+        ];
+        let m = self.mount_matrix();
+        Ok([0, 1, 2].map(|i| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2]))
+    }
+
+    /// `in_accel_mount_matrix` or `mount_matrix`, fallback to identity
+    pub fn mount_matrix(&self) -> [[f64; 3]; 3] {
+        const IDENTITY: [[f64; 3]; 3] = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+        let Ok(s) = self
+            .read_string("in_accel_mount_matrix")
+            .or_else(|_| self.read_string("mount_matrix"))
+        else {
+            return IDENTITY;
+        };
+        parse_mount_matrix(&s).unwrap_or_else(|| {
+            log::warn!("Invalid mount matrix: {s}");
+            IDENTITY
+        })
+    }
+}
+
+/// e.g. `0, 1, 0; 1, 0, 0; 0, 0, 1`
+fn parse_mount_matrix(s: &str) -> Option<[[f64; 3]; 3]> {
+    let mut m = [[0.; 3]; 3];
+    let mut rows = s.split(';');
+    for row in m.iter_mut() {
+        let mut values = rows.next()?.split(',');
+        for value in row.iter_mut() {
+            *value = values.next()?.trim().parse().ok()?;
+        }
+    }
+    Some(m)
+}
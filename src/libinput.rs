@@ -1,17 +1,27 @@
-use anyhow::Result;
+use crate::logind::DeviceClient;
+use anyhow::{Result, anyhow};
 use futures::Stream;
-use input::{Event, Libinput, LibinputInterface};
-use libc::{O_RDONLY, O_RDWR, O_WRONLY};
+use input::{Device, DeviceCapability, Event, Libinput, LibinputInterface};
+use libc::{Ioctl, O_CLOEXEC, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY, ioctl};
 use std::{
     fs::{File, OpenOptions},
+    io,
     ops::{Deref, DerefMut},
-    os::unix::{fs::OpenOptionsExt, io::OwnedFd},
+    os::{
+        fd::AsRawFd,
+        unix::{fs::OpenOptionsExt, io::OwnedFd},
+    },
     path::Path,
     pin::Pin,
     task::{Context, Poll, ready},
 };
 use tokio::io::{Interest, unix::AsyncFd};
 
+/// `_IOC(_IOC_READ, 'E', 0x20 + EV_SW, 4)`
+const EVIOCGBIT_SW: Ioctl = 0x80044525;
+/// `_IOC(_IOC_READ, 'E', 0x1b, 4)`
+const EVIOCGSW: Ioctl = 0x8004451b;
+
 /// a event listener libinput async wrapper
 #[derive(Debug)]
 pub struct EventListener(AsyncFd<Libinput>, ListenerState);
@@ -24,13 +34,23 @@ enum ListenerState {
 }
 
 /// create a new path libinput
-pub fn new_libinput() -> Libinput {
-    Libinput::new_from_path(Interface)
+pub fn new_libinput(interface: Interface) -> Libinput {
+    Libinput::new_from_path(interface)
 }
 
 impl EventListener {
-    pub fn new() -> Result<Self> {
-        let input = AsyncFd::with_interest(new_libinput(), Interest::READABLE)?;
+    pub fn new(interface: Interface) -> Result<Self> {
+        let input = AsyncFd::with_interest(new_libinput(interface), Interest::READABLE)?;
+        Ok(Self(input, Default::default()))
+    }
+
+    /// listen all devices on the seat, include hotplug
+    pub fn new_udev(seat: &str, interface: Interface) -> Result<Self> {
+        let mut input = Libinput::new_with_udev(interface);
+        input
+            .udev_assign_seat(seat)
+            .map_err(|_| anyhow!("Cannot assign seat: {seat}"))?;
+        let input = AsyncFd::with_interest(input, Interest::READABLE)?;
         Ok(Self(input, Default::default()))
     }
 }
@@ -79,10 +99,63 @@ impl Stream for EventListener {
     }
 }
 
-struct Interface;
+/// full keyboard on usb or bluetooth bus
+pub fn is_external_keyboard(device: &Device) -> bool {
+    const KEY_A: u32 = 30;
+
+    if !device.has_capability(DeviceCapability::Keyboard)
+        || device.keyboard_has_key(KEY_A) != Ok(true)
+    {
+        return false;
+    }
+    // SAFETY: the udev device is only used in this scope
+    let Some(udev) = (unsafe { device.udev_device() }) else {
+        return false;
+    };
+    matches!(
+        udev.property_value("ID_BUS").and_then(|bus| bus.to_str()),
+        Some("usb" | "bluetooth")
+    )
+}
+
+/// `SW_*` code and state of the switches on the device
+pub fn switch_state(interface: &mut Interface, path: &Path) -> io::Result<Vec<(u16, bool)>> {
+    let fd = interface
+        .open_restricted(path, O_RDONLY | O_NONBLOCK | O_CLOEXEC)
+        .map_err(io::Error::from_raw_os_error)?;
+    let read = |request, bits: &mut u32| {
+        // SAFETY: `bits` has the size encoded in the request
+        match unsafe { ioctl(fd.as_raw_fd(), request, bits as *mut u32) } {
+            ..0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    };
+    let (mut supported, mut state) = (0u32, 0u32);
+    let res = read(EVIOCGBIT_SW, &mut supported).and_then(|_| read(EVIOCGSW, &mut state));
+    interface.close_restricted(fd);
+    res?;
+    Ok((0..u32::BITS as u16)
+        .filter(|code| supported & (1 << code) != 0)
+        .map(|code| (code, state & (1 << code) != 0))
+        .collect())
+}
+
+/// how libinput opens device
+#[derive(Debug, Clone, Default)]
+pub enum Interface {
+    #[default]
+    Direct,
+    Logind(DeviceClient),
+}
 
 impl LibinputInterface for Interface {
     fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<OwnedFd, i32> {
+        if let Self::Logind(client) = self {
+            return client.open(path).map_err(|err| {
+                log::warn!("Cannot take device {}: {err}", path.display());
+                err.raw_os_error().unwrap_or(libc::EACCES)
+            });
+        }
         OpenOptions::new()
             .custom_flags(flags)
             .read((flags & O_RDONLY != 0) | (flags & O_RDWR != 0))
@@ -92,6 +165,9 @@ impl LibinputInterface for Interface {
             .map_err(|err| err.raw_os_error().unwrap())
     }
     fn close_restricted(&mut self, fd: OwnedFd) {
-        let _ = File::from(fd);
+        match self {
+            Self::Direct => drop(File::from(fd)),
+            Self::Logind(client) => client.close(fd),
+        }
     }
 }
@@ -0,0 +1,884 @@
+use futures::StreamExt;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    future::pending,
+    io,
+    os::{
+        fd::{AsFd, OwnedFd},
+        unix::{
+            fs::{FileTypeExt, MetadataExt},
+            net::UnixStream,
+        },
+    },
+    path::Path,
+    sync::mpsc as std_mpsc,
+    thread,
+};
+use tokio::{
+    select,
+    sync::{mpsc, watch},
+};
+use zbus::{
+    Connection, Result, connection, proxy,
+    proxy::PropertyStream,
+    zvariant::{Fd, OwnedObjectPath},
+};
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+pub trait Manager {
+    /// Inhibit method, the lock is held until the fd is closed
+    fn inhibit(
+        &self,
+        what: &str,
+        who: &str,
+        why: &str,
+        mode: &str,
+    ) -> Result<zbus::zvariant::OwnedFd>;
+
+    /// PrepareForSleep signal, `start` is false after resume
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Seat",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/seat/seat0"
+)]
+pub trait Seat {
+    /// ActiveSession property, the path is `/` if there is no active session
+    #[zbus(property)]
+    fn active_session(&self) -> Result<(String, OwnedObjectPath)>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait Session {
+    /// User property
+    #[zbus(property)]
+    fn user(&self) -> Result<(u32, OwnedObjectPath)>;
+
+    /// Display property, the X11 display
+    #[zbus(property)]
+    fn display(&self) -> Result<String>;
+
+    /// TakeControl method
+    fn take_control(&self, force: bool) -> Result<()>;
+
+    /// TakeDevice method, return the fd and whether the session is inactive
+    fn take_device(&self, major: u32, minor: u32) -> Result<(zbus::zvariant::OwnedFd, bool)>;
+
+    /// ReleaseDevice method
+    fn release_device(&self, major: u32, minor: u32) -> Result<()>;
+
+    /// PauseDeviceComplete method
+    fn pause_device_complete(&self, major: u32, minor: u32) -> Result<()>;
+
+    /// PauseDevice signal, `type_` is `pause`, `force` or `gone`
+    #[zbus(signal)]
+    fn pause_device(&self, major: u32, minor: u32, type_: &str) -> Result<()>;
+
+    /// ResumeDevice signal, the device is reopened with a new fd
+    #[zbus(signal)]
+    fn resume_device(&self, major: u32, minor: u32, fd: Fd<'_>) -> Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.User",
+    default_service = "org.freedesktop.login1"
+)]
+pub trait User {
+    /// RuntimePath property
+    #[zbus(property)]
+    fn runtime_path(&self) -> Result<String>;
+
+    /// Display property, the graphical session of the user
+    #[zbus(property)]
+    fn display(&self) -> Result<(String, OwnedObjectPath)>;
+}
+
+/// the session on screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveSession {
+    pub id: String,
+    pub uid: u32,
+    pub runtime_dir: Option<String>,
+    /// X11 display
+    pub display: Option<String>,
+}
+
+impl ActiveSession {
+    /// `None` if `path` is `/`
+    pub async fn new(conn: &Connection, id: String, path: OwnedObjectPath) -> Result<Option<Self>> {
+        if path.as_str() == "/" {
+            return Ok(None);
+        }
+        let session = SessionProxy::builder(conn).path(path)?.build().await?;
+        let (uid, user) = session.user().await?;
+        let user = UserProxy::builder(conn).path(user)?.build().await?;
+        let non_empty = |s: String| Some(s).filter(|s| !s.is_empty());
+        Ok(Some(Self {
+            id,
+            uid,
+            runtime_dir: non_empty(user.runtime_path().await?),
+            display: non_empty(session.display().await?),
+        }))
+    }
+
+    /// the `wayland-*` socket, looked up every time since the compositor starts after the session
+    ///
+    /// prefer the socket which the compositor is listening on, a leftover socket refuses connection
+    pub fn wayland_display(&self) -> Option<String> {
+        let runtime_dir = Path::new(self.runtime_dir.as_ref()?);
+        let mut sockets = fs::read_dir(runtime_dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|ty| ty.is_socket()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| name.starts_with("wayland-") && !name.ends_with(".lock"))
+            .collect::<Vec<_>>();
+        sockets.sort();
+        let live = sockets
+            .iter()
+            .position(|name| UnixStream::connect(runtime_dir.join(name)).is_ok());
+        match live {
+            Some(i) => Some(sockets.swap_remove(i)),
+            None => sockets.into_iter().next(),
+        }
+    }
+
+    /// `unix:path=$XDG_RUNTIME_DIR/bus` if the socket exists
+    pub fn session_bus(&self) -> Option<String> {
+        let bus = Path::new(self.runtime_dir.as_ref()?).join("bus");
+        fs::metadata(&bus)
+            .is_ok_and(|metadata| metadata.file_type().is_socket())
+            .then(|| format!("unix:path={}", bus.display()))
+    }
+
+    /// `XDG_RUNTIME_DIR`, `DBUS_SESSION_BUS_ADDRESS`, `DISPLAY` and `WAYLAND_DISPLAY`
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        [
+            ("XDG_RUNTIME_DIR", self.runtime_dir.clone()),
+            ("DBUS_SESSION_BUS_ADDRESS", self.session_bus()),
+            ("DISPLAY", self.display.clone()),
+            ("WAYLAND_DISPLAY", self.wayland_display()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
+/// follow the active session of seat0
+pub struct SessionTracker {
+    conn: Connection,
+    seat: SeatProxy<'static>,
+    changed: PropertyStream<'static, (String, OwnedObjectPath)>,
+}
+
+impl SessionTracker {
+    pub async fn new(conn: Connection) -> Result<Self> {
+        let seat = SeatProxy::new(&conn).await?;
+        let mut changed = seat.receive_active_session_changed().await;
+
+        // always have the init vaule
+        let _ = changed.next().await;
+
+        Ok(Self {
+            conn,
+            seat,
+            changed,
+        })
+    }
+
+    pub async fn current(&self) -> Result<Option<ActiveSession>> {
+        let (id, path) = self.seat.active_session().await?;
+        ActiveSession::new(&self.conn, id, path).await
+    }
+
+    /// wait for the session switch, cancel safe
+    pub async fn changed(&mut self) {
+        match self.changed.next().await {
+            Some(_) => {}
+            None => pending().await,
+        }
+    }
+}
+
+/// system sleep, the sleep is delayed until `release`
+pub struct SleepMonitor {
+    manager: ManagerProxy<'static>,
+    signals: PrepareForSleepStream<'static>,
+    lock: Option<zbus::zvariant::OwnedFd>,
+}
+
+impl SleepMonitor {
+    pub async fn new(conn: &Connection) -> Result<Self> {
+        let manager = ManagerProxy::new(conn).await?;
+        let signals = manager.receive_prepare_for_sleep().await?;
+        let mut monitor = Self {
+            manager,
+            signals,
+            lock: None,
+        };
+        monitor.inhibit().await?;
+        Ok(monitor)
+    }
+
+    /// take the delay lock, it is released by logind after the timeout anyway
+    pub async fn inhibit(&mut self) -> Result<()> {
+        let lock = self
+            .manager
+            .inhibit("sleep", "rota", "Run on-suspend", "delay")
+            .await?;
+        self.lock = Some(lock);
+        Ok(())
+    }
+
+    /// allow the system to sleep
+    pub fn release(&mut self) {
+        self.lock = None;
+    }
+
+    /// `true` before sleep, `false` after resume, cancel safe
+    pub async fn next(&mut self) -> Result<bool> {
+        match self.signals.next().await {
+            Some(signal) => Ok(signal.args()?.start),
+            None => pending().await,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum DeviceRequest {
+    Take(u32, u32, std_mpsc::SyncSender<io::Result<OwnedFd>>),
+    Release(u32, u32),
+}
+
+/// a device taken from the session
+#[derive(Debug)]
+struct TakenDevice {
+    /// replaced by the new fd of `ResumeDevice`
+    fd: OwnedFd,
+    /// number of fds opened by libinput
+    users: usize,
+    /// revoked by `PauseDevice` until `ResumeDevice`
+    paused: bool,
+}
+
+/// open device through logind `TakeDevice`, so rota can run without root
+///
+/// the session is controlled on its own thread, since libinput opens device synchronously
+#[derive(Debug, Clone)]
+pub struct DeviceClient {
+    requests: mpsc::UnboundedSender<DeviceRequest>,
+}
+
+impl DeviceClient {
+    /// take control of the graphical session of current user,
+    /// the receiver is false while any taken device is paused
+    pub fn spawn() -> anyhow::Result<(Self, watch::Receiver<bool>)> {
+        Self::spawn_at(None)
+    }
+
+    /// `None` for the system bus
+    fn spawn_at(address: Option<String>) -> anyhow::Result<(Self, watch::Receiver<bool>)> {
+        let (requests, requests_r) = mpsc::unbounded_channel();
+        let (active, active_r) = watch::channel(true);
+        let (init, init_r) = std_mpsc::sync_channel::<anyhow::Result<()>>(1);
+        thread::Builder::new()
+            .name("logind".to_owned())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(err) => {
+                        let _ = init.send(Err(err.into()));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    let session = match take_control(address).await {
+                        Ok(session) => session,
+                        Err(err) => {
+                            let _ = init.send(Err(err.into()));
+                            return;
+                        }
+                    };
+                    let _ = init.send(Ok(()));
+                    if let Err(err) = serve_devices(session, requests_r, active).await {
+                        log::error!("Logind session error: {err}");
+                    }
+                });
+            })?;
+        init_r.recv()??;
+        Ok((Self { requests }, active_r))
+    }
+
+    /// blocking
+    pub fn open(&self, path: &Path) -> io::Result<OwnedFd> {
+        let rdev = fs::metadata(path)?.rdev();
+        let (reply, reply_r) = std_mpsc::sync_channel(1);
+        let closed = || io::Error::other("Logind session is closed");
+        self.requests
+            .send(DeviceRequest::Take(
+                libc::major(rdev),
+                libc::minor(rdev),
+                reply,
+            ))
+            .map_err(|_| closed())?;
+        reply_r.recv().map_err(|_| closed())?
+    }
+
+    pub fn close(&self, fd: OwnedFd) {
+        let Ok(metadata) = File::from(fd).metadata() else {
+            return;
+        };
+        let rdev = metadata.rdev();
+        let _ = self
+            .requests
+            .send(DeviceRequest::Release(libc::major(rdev), libc::minor(rdev)));
+    }
+}
+
+async fn take_control(address: Option<String>) -> Result<SessionProxy<'static>> {
+    let conn = match address {
+        Some(address) => {
+            connection::Builder::address(address.as_str())?
+                .build()
+                .await?
+        }
+        None => Connection::system().await?,
+    };
+    let user = UserProxy::builder(&conn)
+        .path("/org/freedesktop/login1/user/self")?
+        .build()
+        .await?;
+    let (id, path) = user.display().await?;
+    if path.as_str() == "/" {
+        return Err(zbus::Error::Failure(
+            "No graphical session of current user".to_owned(),
+        ));
+    }
+    let session = SessionProxy::builder(&conn).path(path)?.build().await?;
+    session.take_control(false).await?;
+    log::info!("Take control of session {id}");
+    Ok(session)
+}
+
+/// a device is taken once and shared by all libinput contexts,
+/// logind fails to take a device twice
+async fn take_device(
+    session: &SessionProxy<'_>,
+    devices: &mut HashMap<(u32, u32), TakenDevice>,
+    major: u32,
+    minor: u32,
+) -> io::Result<OwnedFd> {
+    if let Some(device) = devices.get_mut(&(major, minor)) {
+        if device.paused {
+            return Err(io::Error::from_raw_os_error(libc::EAGAIN));
+        }
+        let fd = device.fd.try_clone()?;
+        device.users += 1;
+        return Ok(fd);
+    }
+    let (fd, inactive) = session
+        .take_device(major, minor)
+        .await
+        .map_err(io::Error::other)?;
+    let fd = OwnedFd::from(fd);
+    devices.insert(
+        (major, minor),
+        TakenDevice {
+            fd: fd.try_clone()?,
+            users: 1,
+            paused: inactive,
+        },
+    );
+    Ok(fd)
+}
+
+async fn serve_devices(
+    session: SessionProxy<'static>,
+    mut requests: mpsc::UnboundedReceiver<DeviceRequest>,
+    active: watch::Sender<bool>,
+) -> Result<()> {
+    let mut pause = session.receive_pause_device().await?;
+    let mut resume = session.receive_resume_device().await?;
+    let mut devices = HashMap::new();
+    loop {
+        select! {
+            request = requests.recv() => match request {
+                Some(DeviceRequest::Take(major, minor, reply)) => {
+                    let _ = reply.send(take_device(&session, &mut devices, major, minor).await);
+                }
+                Some(DeviceRequest::Release(major, minor)) => {
+                    let Some(device) = devices.get_mut(&(major, minor)) else {
+                        continue;
+                    };
+                    device.users = device.users.saturating_sub(1);
+                    // a paused device is kept for the fd of `ResumeDevice`
+                    if device.users > 0 || device.paused {
+                        continue;
+                    }
+                    devices.remove(&(major, minor));
+                    if let Err(err) = session.release_device(major, minor).await {
+                        log::warn!("Cannot release device {major}:{minor}: {err}");
+                    }
+                }
+                // all libinput contexts are dropped
+                None => return Ok(()),
+            },
+            Some(signal) = pause.next() => {
+                let args = signal.args()?;
+                log::debug!("Pause device {}:{}: {}", args.major, args.minor, args.type_);
+                let device = (args.major, args.minor);
+                match args.type_ {
+                    // logind already released it
+                    "gone" => drop(devices.remove(&device)),
+                    type_ => {
+                        if let Some(device) = devices.get_mut(&device) {
+                            device.paused = true;
+                        }
+                        // the device is already revoked on `force`
+                        if type_ == "pause" {
+                            session.pause_device_complete(args.major, args.minor).await?;
+                        }
+                    }
+                }
+            }
+            Some(signal) = resume.next() => {
+                let args = signal.args()?;
+                log::debug!("Resume device {}:{}", args.major, args.minor);
+                if let Some(device) = devices.get_mut(&(args.major, args.minor)) {
+                    device.fd = args.fd.as_fd().try_clone_to_owned()?;
+                    device.paused = false;
+                }
+            }
+        }
+        // libinput reopens the devices after all are resumed
+        let resumed = !devices.values().any(|device| device.paused);
+        active.send_if_modified(|active| std::mem::replace(active, resumed) != resumed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::create_dir_all, os::unix::net::UnixListener, process::Stdio, time::Duration};
+    use tokio::{
+        io::{AsyncBufReadExt, BufReader},
+        process::Command,
+        time::timeout,
+    };
+    use zbus::{interface, object_server::SignalContext, zvariant::ObjectPath};
+
+    struct FakeSeat {
+        session: (String, OwnedObjectPath),
+    }
+
+    #[interface(name = "org.freedesktop.login1.Seat")]
+    impl FakeSeat {
+        #[zbus(property)]
+        fn active_session(&self) -> (String, OwnedObjectPath) {
+            self.session.clone()
+        }
+    }
+
+    struct FakeSession {
+        uid: u32,
+        display: String,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Session")]
+    impl FakeSession {
+        #[zbus(property)]
+        fn user(&self) -> (u32, OwnedObjectPath) {
+            let path = format!("/org/freedesktop/login1/user/_{}", self.uid);
+            (self.uid, ObjectPath::try_from(path).unwrap().into())
+        }
+
+        #[zbus(property)]
+        fn display(&self) -> String {
+            self.display.clone()
+        }
+    }
+
+    struct FakeUser {
+        runtime_path: String,
+    }
+
+    #[interface(name = "org.freedesktop.login1.User")]
+    impl FakeUser {
+        #[zbus(property)]
+        fn runtime_path(&self) -> String {
+            self.runtime_path.clone()
+        }
+
+        #[zbus(property)]
+        fn display(&self) -> (String, OwnedObjectPath) {
+            ("1".to_owned(), session_path("1"))
+        }
+    }
+
+    /// controlled session, log the device calls
+    struct FakeControlSession {
+        active: bool,
+        calls: mpsc::UnboundedSender<String>,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Session")]
+    impl FakeControlSession {
+        fn take_control(&self, force: bool) {
+            let _ = self.calls.send(format!("take-control {force}"));
+        }
+
+        fn take_device(&self, major: u32, minor: u32) -> (zbus::zvariant::OwnedFd, bool) {
+            let _ = self.calls.send(format!("take {major}:{minor}"));
+            let fd = OwnedFd::from(File::open("/dev/null").unwrap());
+            (fd.into(), !self.active)
+        }
+
+        fn release_device(&self, major: u32, minor: u32) {
+            let _ = self.calls.send(format!("release {major}:{minor}"));
+        }
+
+        fn pause_device_complete(&self, major: u32, minor: u32) {
+            let _ = self.calls.send(format!("pause-complete {major}:{minor}"));
+        }
+
+        #[zbus(signal)]
+        async fn pause_device(
+            ctxt: &SignalContext<'_>,
+            major: u32,
+            minor: u32,
+            type_: &str,
+        ) -> Result<()>;
+
+        #[zbus(signal)]
+        async fn resume_device(
+            ctxt: &SignalContext<'_>,
+            major: u32,
+            minor: u32,
+            fd: Fd<'_>,
+        ) -> Result<()>;
+    }
+
+    /// log the inhibitor calls
+    struct FakeManager {
+        calls: mpsc::UnboundedSender<String>,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Manager")]
+    impl FakeManager {
+        fn inhibit(
+            &self,
+            what: &str,
+            who: &str,
+            _why: &str,
+            mode: &str,
+        ) -> zbus::zvariant::OwnedFd {
+            let _ = self.calls.send(format!("inhibit {what} {who} {mode}"));
+            OwnedFd::from(File::open("/dev/null").unwrap()).into()
+        }
+
+        #[zbus(signal)]
+        async fn prepare_for_sleep(ctxt: &SignalContext<'_>, start: bool) -> Result<()>;
+    }
+
+    /// stand-in bus for logind, the tests fail without dbus-daemon
+    async fn private_bus() -> (tokio::process::Child, String) {
+        let mut bus = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .expect("dbus-daemon is required by the logind tests");
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .await
+            .unwrap();
+        (bus, address.trim().to_owned())
+    }
+
+    fn session_path(id: &str) -> OwnedObjectPath {
+        ObjectPath::try_from(format!("/org/freedesktop/login1/session/_3{id}"))
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn follow_session() {
+        // stand-in logind on a private bus
+        let (_bus, address) = private_bus().await;
+
+        let runtime_dir = std::env::temp_dir().join(format!("rota-logind-{}", std::process::id()));
+        create_dir_all(&runtime_dir).unwrap();
+        let _socket = UnixListener::bind(runtime_dir.join("wayland-1")).unwrap();
+        fs::write(runtime_dir.join("wayland-1.lock"), "").unwrap();
+        // leftover of a dead compositor
+        drop(UnixListener::bind(runtime_dir.join("wayland-0")).unwrap());
+        fs::write(runtime_dir.join("wayland-0.lock"), "").unwrap();
+        let _bus = UnixListener::bind(runtime_dir.join("bus")).unwrap();
+
+        let seat_path = "/org/freedesktop/login1/seat/seat0";
+        let logind = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at(
+                seat_path,
+                FakeSeat {
+                    session: ("1".to_owned(), session_path("1")),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                session_path("1"),
+                FakeSession {
+                    uid: 1000,
+                    display: String::new(),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                session_path("2"),
+                FakeSession {
+                    uid: 1001,
+                    display: ":1".to_owned(),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/login1/user/_1000",
+                FakeUser {
+                    runtime_path: runtime_dir.to_str().unwrap().to_owned(),
+                },
+            )
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/login1/user/_1001",
+                FakeUser {
+                    runtime_path: "/nonexistent".to_owned(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let switch = |id: &str| {
+            let logind = logind.clone();
+            let session = match id {
+                "" => ("".to_owned(), ObjectPath::try_from("/").unwrap().into()),
+                id => (id.to_owned(), session_path(id)),
+            };
+            async move {
+                let seat = logind
+                    .object_server()
+                    .interface::<_, FakeSeat>(seat_path)
+                    .await
+                    .unwrap();
+                seat.get_mut().await.session = session;
+                seat.get()
+                    .await
+                    .active_session_changed(seat.signal_context())
+                    .await
+                    .unwrap();
+            }
+        };
+
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let mut tracker = SessionTracker::new(client).await.unwrap();
+        let session = tracker.current().await.unwrap().unwrap();
+        assert_eq!(session.uid, 1000);
+        assert_eq!(session.display, None);
+        assert_eq!(session.wayland_display().as_deref(), Some("wayland-1"));
+        assert_eq!(
+            session.session_bus(),
+            Some(format!("unix:path={}/bus", runtime_dir.display()))
+        );
+
+        switch("2").await;
+        timeout(Duration::from_secs(5), tracker.changed())
+            .await
+            .unwrap();
+        let session = tracker.current().await.unwrap().unwrap();
+        assert_eq!(session.id, "2");
+        assert_eq!(
+            session.env(),
+            [
+                ("XDG_RUNTIME_DIR", "/nonexistent".to_owned()),
+                ("DISPLAY", ":1".to_owned()),
+            ]
+        );
+
+        switch("").await;
+        timeout(Duration::from_secs(5), tracker.changed())
+            .await
+            .unwrap();
+        assert_eq!(tracker.current().await.unwrap(), None);
+
+        fs::remove_dir_all(&runtime_dir).unwrap();
+    }
+
+    async fn next_call(calls: &mut mpsc::UnboundedReceiver<String>) -> String {
+        timeout(Duration::from_secs(5), calls.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn take_device() {
+        let (_bus, address) = private_bus().await;
+        let (calls, mut calls_r) = mpsc::unbounded_channel();
+        let logind = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at(
+                session_path("1"),
+                FakeControlSession {
+                    active: true,
+                    calls,
+                },
+            )
+            .unwrap()
+            .serve_at(
+                "/org/freedesktop/login1/user/self",
+                FakeUser {
+                    runtime_path: String::new(),
+                },
+            )
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let (client, mut active) =
+            tokio::task::spawn_blocking(move || DeviceClient::spawn_at(Some(address)))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(next_call(&mut calls_r).await, "take-control false");
+
+        let open = |path: &'static str| {
+            let client = client.clone();
+            async move {
+                tokio::task::spawn_blocking(move || client.open(Path::new(path)))
+                    .await
+                    .unwrap()
+            }
+        };
+        let null = fs::metadata("/dev/null").unwrap().rdev();
+        let (major, minor) = (libc::major(null), libc::minor(null));
+        let device = format!("{major}:{minor}");
+        let fd = open("/dev/null").await.unwrap();
+        assert_eq!(next_call(&mut calls_r).await, format!("take {device}"));
+        // shared with the other libinput contexts, not taken again
+        let shared = open("/dev/null").await.unwrap();
+
+        let session = logind
+            .object_server()
+            .interface::<_, FakeControlSession>(session_path("1"))
+            .await
+            .unwrap();
+        FakeControlSession::pause_device(session.signal_context(), major, minor, "pause")
+            .await
+            .unwrap();
+        assert_eq!(
+            next_call(&mut calls_r).await,
+            format!("pause-complete {device}")
+        );
+        timeout(Duration::from_secs(5), active.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!*active.borrow_and_update());
+        assert!(open("/dev/null").await.is_err());
+
+        // kept while paused
+        client.close(fd);
+        client.close(shared);
+
+        // write only to tell it from the fd of `TakeDevice`
+        let resumed = File::options().write(true).open("/dev/null").unwrap();
+        FakeControlSession::resume_device(
+            session.signal_context(),
+            major,
+            minor,
+            resumed.as_fd().into(),
+        )
+        .await
+        .unwrap();
+        timeout(Duration::from_secs(5), active.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(*active.borrow_and_update());
+
+        // reopened with the fd of `ResumeDevice`
+        let fd = open("/dev/null").await.unwrap();
+        assert_eq!(
+            File::from(fd.try_clone().unwrap())
+                .metadata()
+                .unwrap()
+                .rdev(),
+            resumed.metadata().unwrap().rdev()
+        );
+        client.close(fd);
+        assert_eq!(next_call(&mut calls_r).await, format!("release {device}"));
+    }
+
+    #[tokio::test]
+    async fn sleep_monitor() {
+        let (_bus, address) = private_bus().await;
+        let (calls, mut calls_r) = mpsc::unbounded_channel();
+        let logind = connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.login1")
+            .unwrap()
+            .serve_at("/org/freedesktop/login1", FakeManager { calls })
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let client = connection::Builder::address(address.as_str())
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let mut sleep = SleepMonitor::new(&client).await.unwrap();
+        assert_eq!(next_call(&mut calls_r).await, "inhibit sleep rota delay");
+        assert!(sleep.lock.is_some());
+
+        let manager = logind
+            .object_server()
+            .interface::<_, FakeManager>("/org/freedesktop/login1")
+            .await
+            .unwrap();
+        for start in [true, false] {
+            FakeManager::prepare_for_sleep(manager.signal_context(), start)
+                .await
+                .unwrap();
+            let signal = timeout(Duration::from_secs(5), sleep.next()).await;
+            assert_eq!(signal.unwrap().unwrap(), start);
+        }
+        sleep.release();
+        assert!(sleep.lock.is_none());
+    }
+}
@@ -1,11 +1,24 @@
+mod binding;
+mod calibration;
+mod cli;
 mod config;
+mod gesture;
 mod iio;
+mod keys;
 mod libinput;
+mod logind;
+mod mode;
+mod orientation;
+mod remap;
 mod runtime;
+mod uinput;
+mod user;
+mod util;
 
 use std::env::args;
 
 use anyhow::{Context, Result};
+use calibration::RotationMode;
 use config::Config;
 use runtime::Runtime;
 use tokio::fs::read_to_string;
@@ -14,10 +27,27 @@ use tokio::fs::read_to_string;
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let config_path = args()
-        .nth(1)
+    let mut args = args().skip(1);
+    let config_path = args
+        .next()
         .context("Require config file path: rota {path}")?;
 
+    match config_path.as_str() {
+        "list-devices" => return cli::list_devices::list_devices().await,
+        "calibrate" => {
+            let touchscreen = args
+                .next()
+                .context("Require touchscreen path: rota calibrate {path} {rotation}")?;
+            let rotation = args
+                .next()
+                .map(|s| s.parse::<RotationMode>())
+                .transpose()?
+                .unwrap_or(RotationMode::Normal);
+            return cli::calibrate::calibrate(&touchscreen, rotation).await;
+        }
+        _ => {}
+    }
+
     let buf = read_to_string(&config_path).await?;
 
     let con: Config = knus::parse(&config_path, &buf)?;
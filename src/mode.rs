@@ -0,0 +1,196 @@
+use crate::{
+    config::{self, KeyboardState, LidState, SettingMode},
+    iio::sensor_proxy::{AccelerometerOrientation, AccelerometerTilt},
+    orientation::parse_orientation,
+};
+use anyhow::{Result, anyhow};
+
+/// all inputs which mode rules can depend on
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputState {
+    pub switch: SettingMode,
+    pub lid: LidState,
+    /// `None` before the first accelerometer event
+    pub orientation: Option<AccelerometerOrientation>,
+    pub keyboard: KeyboardState,
+    /// `None` if iio-sensor-proxy does not support it
+    pub tilt: Option<AccelerometerTilt>,
+    /// `None` if there is no hinge sensor
+    pub hinge: Option<f64>,
+}
+
+impl InputState {
+    pub fn new(switch: SettingMode) -> Self {
+        Self {
+            switch,
+            lid: LidState::Open,
+            orientation: None,
+            keyboard: KeyboardState::Absent,
+            tilt: None,
+            hinge: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Rule {
+    Switch(SettingMode),
+    Lid(LidState),
+    Orientation(Vec<AccelerometerOrientation>),
+    Keyboard(KeyboardState),
+    Tilt(Vec<AccelerometerTilt>),
+    HingeAbove(f64),
+    HingeBelow(f64),
+}
+
+impl Rule {
+    fn matches(&self, state: &InputState) -> bool {
+        match self {
+            Rule::Switch(switch) => state.switch == *switch,
+            Rule::Lid(lid) => state.lid == *lid,
+            Rule::Orientation(orientations) => state
+                .orientation
+                .as_ref()
+                .is_some_and(|orientation| orientations.contains(orientation)),
+            Rule::Keyboard(keyboard) => state.keyboard == *keyboard,
+            Rule::Tilt(tilts) => state.tilt.as_ref().is_some_and(|tilt| tilts.contains(tilt)),
+            Rule::HingeAbove(angle) => state.hinge.is_some_and(|hinge| hinge > *angle),
+            Rule::HingeBelow(angle) => state.hinge.is_some_and(|hinge| hinge < *angle),
+        }
+    }
+}
+
+/// user defined modes, in config order
+#[derive(Debug, Default)]
+pub struct ModeRules(Vec<(String, Vec<Rule>)>);
+
+impl ModeRules {
+    pub fn new(modes: Vec<config::Mode>) -> Result<Self> {
+        let modes = modes
+            .into_iter()
+            .map(|mode| {
+                let rules = mode
+                    .rules
+                    .into_iter()
+                    .map(Rule::try_from)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((mode.name, rules))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self(modes))
+    }
+
+    /// all mode names, include the builtin
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        ["laptop", "tablet"]
+            .into_iter()
+            .chain(self.0.iter().map(|(name, _)| name.as_str()))
+    }
+
+    /// should track external keyboard or not
+    pub fn use_keyboard(&self) -> bool {
+        self.0
+            .iter()
+            .flat_map(|(_, rules)| rules)
+            .any(|rule| matches!(rule, Rule::Keyboard(_)))
+    }
+
+    /// first matched mode, fallback to the switch mode
+    pub fn derive(&self, state: &InputState) -> &str {
+        self.0
+            .iter()
+            .find(|(_, rules)| rules.iter().all(|rule| rule.matches(state)))
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(match state.switch {
+                SettingMode::Laptop => "laptop",
+                SettingMode::Tablet => "tablet",
+            })
+    }
+}
+
+impl TryFrom<config::ModeRule> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(value: config::ModeRule) -> Result<Self> {
+        let rule = match value {
+            config::ModeRule::Switch(switch) => Rule::Switch(switch),
+            config::ModeRule::Lid(lid) => Rule::Lid(lid),
+            config::ModeRule::Orientation(ss) => Rule::Orientation(
+                ss.iter()
+                    .map(|s| parse_orientation(s))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            config::ModeRule::Keyboard(keyboard) => Rule::Keyboard(keyboard),
+            config::ModeRule::Tilt(ss) => Rule::Tilt(
+                ss.iter()
+                    .map(|s| {
+                        AccelerometerTilt::ALL
+                            .into_iter()
+                            .find(|tilt| tilt.as_str() == s)
+                            .ok_or_else(|| anyhow!("Unknown value for tilt: {s}"))
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            config::ModeRule::HingeAbove(angle) => Rule::HingeAbove(angle as f64),
+            config::ModeRule::HingeBelow(angle) => Rule::HingeBelow(angle as f64),
+        };
+        Ok(rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_mode() {
+        let modes = ModeRules(vec![
+            (
+                "tent".to_owned(),
+                vec![
+                    Rule::Switch(SettingMode::Tablet),
+                    Rule::Orientation(vec![AccelerometerOrientation::BottomUp]),
+                ],
+            ),
+            (
+                "docked".to_owned(),
+                vec![Rule::Keyboard(KeyboardState::Present)],
+            ),
+        ]);
+        let mut state = InputState::new(SettingMode::Laptop);
+        assert_eq!(modes.derive(&state), "laptop");
+
+        state.switch = SettingMode::Tablet;
+        assert_eq!(modes.derive(&state), "tablet");
+
+        state.orientation = Some(AccelerometerOrientation::BottomUp);
+        assert_eq!(modes.derive(&state), "tent");
+
+        // first match win
+        state.keyboard = KeyboardState::Present;
+        assert_eq!(modes.derive(&state), "tent");
+
+        state.orientation = Some(AccelerometerOrientation::Normal);
+        assert_eq!(modes.derive(&state), "docked");
+    }
+
+    #[test]
+    fn derive_tilt_mode() {
+        let rule = config::ModeRule::Tilt(vec!["face-up".to_owned(), "face-down".to_owned()]);
+        let modes = ModeRules(vec![(
+            "flat".to_owned(),
+            vec![Rule::try_from(rule).unwrap()],
+        )]);
+        let mut state = InputState::new(SettingMode::Laptop);
+        assert_eq!(modes.derive(&state), "laptop");
+
+        state.tilt = Some(AccelerometerTilt::FaceDown);
+        assert_eq!(modes.derive(&state), "flat");
+
+        state.tilt = Some(AccelerometerTilt::Vertical);
+        assert_eq!(modes.derive(&state), "laptop");
+
+        let rule = config::ModeRule::Tilt(vec!["sideways".to_owned()]);
+        assert!(Rule::try_from(rule).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{config, iio::sensor_proxy::AccelerometerOrientation};
+use anyhow::{Result, anyhow};
+use std::{future::pending, time::Duration};
+use tokio::time::{Instant, sleep_until};
+
+/// parse a known orientation, e.g. `left-up`
+pub fn parse_orientation(s: &str) -> Result<AccelerometerOrientation> {
+    match s.parse().unwrap() {
+        AccelerometerOrientation::Unknown(s) => Err(anyhow!("Unknown value for orientation: {s}")),
+        orientation => Ok(orientation),
+    }
+}
+
+/// remap the sensor orientation, then drop the disallowed one
+#[derive(Debug, Default)]
+pub struct OrientationPolicy {
+    remap: Vec<(AccelerometerOrientation, AccelerometerOrientation)>,
+    /// `None` if all orientations are allowed
+    allowed: Option<Vec<AccelerometerOrientation>>,
+}
+
+impl OrientationPolicy {
+    pub fn new(
+        remap: Option<config::OrientationMap>,
+        allowed: Option<Vec<String>>,
+    ) -> Result<Self> {
+        let remap = remap
+            .map(|map| map.entries)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| {
+                Ok((
+                    parse_orientation(&entry.from)?,
+                    parse_orientation(&entry.to)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let allowed = allowed
+            .map(|allowed| {
+                allowed
+                    .iter()
+                    .map(|s| match s.as_str() {
+                        "landscape" => Ok(vec![
+                            AccelerometerOrientation::Normal,
+                            AccelerometerOrientation::BottomUp,
+                        ]),
+                        "portrait" => Ok(vec![
+                            AccelerometerOrientation::LeftUp,
+                            AccelerometerOrientation::RightUp,
+                        ]),
+                        s => parse_orientation(s).map(|orientation| vec![orientation]),
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .map(|allowed| allowed.concat())
+            })
+            .transpose()?;
+        Ok(Self { remap, allowed })
+    }
+
+    /// sensor orientation to the real orientation
+    pub fn remap(&self, orientation: AccelerometerOrientation) -> AccelerometerOrientation {
+        self.remap
+            .iter()
+            .find(|(from, _)| *from == orientation)
+            .map(|(_, to)| to.clone())
+            .unwrap_or(orientation)
+    }
+
+    /// should rotate to the remapped orientation, `undefined` is always allowed
+    pub fn allows(&self, orientation: &AccelerometerOrientation) -> bool {
+        match (&self.allowed, orientation) {
+            (_, AccelerometerOrientation::Undefined | AccelerometerOrientation::Unknown(_)) => true,
+            (Some(allowed), orientation) => allowed.contains(orientation),
+            (None, _) => true,
+        }
+    }
+}
+
+/// orientation waiting for all touch and pen contacts released
+#[derive(Debug, Default)]
+pub struct DeferredRotation {
+    orientation: Option<AccelerometerOrientation>,
+    /// end of grace period after all contacts released
+    deadline: Option<Instant>,
+}
+
+impl DeferredRotation {
+    /// should defer the new orientation, also during the grace period
+    pub fn should_defer(&self, active: bool) -> bool {
+        active || self.deadline.is_some()
+    }
+
+    pub fn defer(&mut self, orientation: AccelerometerOrientation) {
+        self.orientation = Some(orientation);
+    }
+
+    /// drop the waiting orientation, e.g. rotation is disabled
+    pub fn clear(&mut self) {
+        self.orientation = None;
+        self.deadline = None;
+    }
+
+    /// start the grace period when all contacts released, stop it otherwise
+    pub fn update_contacts(&mut self, active: bool, grace: Duration) {
+        if active {
+            self.deadline = None;
+            return;
+        }
+        if self.orientation.is_some() && self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + grace);
+        }
+    }
+
+    /// wait for the end of grace period, cancel safe
+    pub async fn expired(&mut self) -> Option<AccelerometerOrientation> {
+        match self.deadline {
+            Some(deadline) => sleep_until(deadline).await,
+            None => pending().await,
+        }
+        self.deadline = None;
+        self.orientation.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+
+    #[test]
+    fn policy() {
+        use AccelerometerOrientation::*;
+
+        let policy = OrientationPolicy::new(
+            Some(config::OrientationMap {
+                entries: vec![
+                    config::OrientationMapEntry {
+                        from: "normal".to_owned(),
+                        to: "left-up".to_owned(),
+                    },
+                    config::OrientationMapEntry {
+                        from: "left-up".to_owned(),
+                        to: "bottom-up".to_owned(),
+                    },
+                ],
+            }),
+            Some(vec!["portrait".to_owned(), "normal".to_owned()]),
+        )
+        .unwrap();
+        assert_eq!(policy.remap(Normal), LeftUp);
+        assert_eq!(policy.remap(LeftUp), BottomUp);
+        assert_eq!(policy.remap(RightUp), RightUp);
+        assert!(policy.allows(&LeftUp));
+        assert!(policy.allows(&RightUp));
+        assert!(!policy.allows(&BottomUp));
+        assert!(policy.allows(&Undefined));
+
+        assert!(OrientationPolicy::new(None, Some(vec!["upside-down".to_owned()])).is_err());
+        assert!(OrientationPolicy::default().allows(&BottomUp));
+    }
+
+    #[tokio::test]
+    async fn deferred_rotation() {
+        use AccelerometerOrientation::*;
+
+        let grace = Duration::from_millis(10);
+        let mut deferred = DeferredRotation::default();
+        assert!(deferred.should_defer(true));
+        assert!(!deferred.should_defer(false));
+
+        deferred.defer(LeftUp);
+        deferred.update_contacts(true, grace);
+        assert!(timeout(grace * 5, deferred.expired()).await.is_err());
+        deferred.update_contacts(false, grace);
+        assert!(deferred.should_defer(false));
+        assert_eq!(deferred.expired().await, Some(LeftUp));
+        assert!(!deferred.should_defer(false));
+
+        // rotation is disabled before the contacts released
+        deferred.defer(RightUp);
+        deferred.update_contacts(false, grace);
+        deferred.clear();
+        assert!(timeout(grace * 5, deferred.expired()).await.is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{
+    config,
+    keys::key_code,
+    uinput::{EV_KEY, EV_SYN, SYN_REPORT, VirtualDevice},
+};
+use anyhow::{Context as _, Result, anyhow};
+use futures::Stream;
+use libc::{Ioctl, c_int, input_event, ioctl};
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, Read},
+    mem::size_of,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    pin::Pin,
+    slice,
+    task::{Context, Poll, ready},
+};
+use tokio::io::{Interest, unix::AsyncFd};
+
+/// `_IOW('E', 0x90, int)`
+const EVIOCGRAB: Ioctl = 0x40044590;
+/// `_IOC(_IOC_READ, 'E', 0x20 + EV_KEY, KEY_BITS)`
+const EVIOCGBIT_KEY: Ioctl = 0x80604521;
+/// `(KEY_MAX + 1) / 8`
+const KEY_BITS: usize = 96;
+
+/// remap keys of a keyboard per mode
+///
+/// the keyboard is grabbed only if current mode has a table,
+/// mapped keys are emitted from a virtual keyboard
+#[derive(Debug)]
+pub struct KeyRemap {
+    path: String,
+    device: Option<AsyncFd<File>>,
+    output: VirtualDevice,
+    tables: BTreeMap<String, BTreeMap<u16, u16>>,
+    mapper: KeyMapper,
+}
+
+/// map the key events of the grabbed keyboard
+#[derive(Debug, Default)]
+struct KeyMapper {
+    table: BTreeMap<u16, u16>,
+    /// physical key to the emitted key, so it is released even if table changed
+    pressed: BTreeMap<u16, u16>,
+    /// events until next `SYN_REPORT`
+    frame: Vec<(u16, u16, i32)>,
+}
+
+impl KeyMapper {
+    /// the mapped frame at `SYN_REPORT`
+    fn push(&mut self, type_: u16, code: u16, value: i32) -> Option<Vec<(u16, u16, i32)>> {
+        match (type_, code) {
+            (EV_KEY, code) => {
+                let mapped = self.table.get(&code).copied().unwrap_or(code);
+                let mapped = match value {
+                    0 => self.pressed.remove(&code).unwrap_or(mapped),
+                    1 => *self.pressed.entry(code).insert_entry(mapped).get(),
+                    _ => self.pressed.get(&code).copied().unwrap_or(mapped),
+                };
+                self.frame.push((EV_KEY, mapped, value));
+                None
+            }
+            (EV_SYN, SYN_REPORT) if !self.frame.is_empty() => Some(std::mem::take(&mut self.frame)),
+            // scan codes and leds are not forwarded
+            _ => None,
+        }
+    }
+
+    /// release the emitted keys which are still pressed
+    fn release_all(&mut self) -> Vec<(u16, u16, i32)> {
+        std::mem::take(&mut self.pressed)
+            .into_values()
+            .map(|code| (EV_KEY, code, 0))
+            .collect()
+    }
+}
+
+impl KeyRemap {
+    pub fn new(path: String, remaps: Vec<config::Remap>) -> Result<Self> {
+        let mut tables = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for remap in remaps {
+            let table = tables.entry(remap.mode).or_default();
+            for entry in remap.entries {
+                let code = |key: &str| {
+                    key_code(key)
+                        .and_then(|code| u16::try_from(code).ok())
+                        .ok_or_else(|| anyhow!("Unknown key: {key}"))
+                };
+                table.insert(code(&entry.from)?, code(&entry.to)?);
+            }
+        }
+
+        let device = File::open(&path).with_context(|| format!("Cannot open keyboard: {path}"))?;
+        let mut bits = [0u8; KEY_BITS];
+        // SAFETY: `bits` has the size encoded in the request
+        if unsafe { ioctl(device.as_raw_fd(), EVIOCGBIT_KEY, bits.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error()).context("Cannot get keyboard keys");
+        }
+        let mut codes = (0..KEY_BITS as u16 * 8)
+            .filter(|code| bits[*code as usize / 8] & (1 << (code % 8)) != 0)
+            .chain(tables.values().flat_map(|table| table.values().copied()))
+            .map(|code| (EV_KEY, code))
+            .collect::<Vec<_>>();
+        codes.sort();
+        codes.dedup();
+        let output = VirtualDevice::new("rota virtual keyboard", &codes)
+            .context("Cannot create virtual keyboard")?;
+
+        Ok(Self {
+            path,
+            device: None,
+            output,
+            tables,
+            mapper: KeyMapper::default(),
+        })
+    }
+
+    /// grab the keyboard if `mode` has a table, otherwise release it,
+    /// the keyboard stays released on error
+    pub fn set_mode(&mut self, mode: &str) -> Result<()> {
+        let Some(table) = self.tables.get(mode) else {
+            if self.device.take().is_some() {
+                log::info!("Release keyboard: {}", self.path);
+                let events = self.mapper.release_all();
+                if !events.is_empty() {
+                    self.output.emit(&events)?;
+                }
+            }
+            return Ok(());
+        };
+        if self.device.is_some() {
+            self.mapper.table = table.clone();
+            return Ok(());
+        }
+
+        let device = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.path)
+            .with_context(|| format!("Cannot open keyboard: {}", self.path))?;
+        // SAFETY: the argument is passed by value
+        if unsafe { ioctl(device.as_raw_fd(), EVIOCGRAB, 1 as c_int) } < 0 {
+            return Err(io::Error::last_os_error()).context("Cannot grab keyboard");
+        }
+        log::info!("Grab keyboard: {}", self.path);
+        // the grab is released on close
+        self.device = Some(AsyncFd::with_interest(device, Interest::READABLE)?);
+        self.mapper.table = table.clone();
+        self.mapper.frame.clear();
+        Ok(())
+    }
+
+    fn forward(&mut self, events: &[input_event]) -> io::Result<()> {
+        for event in events {
+            if let Some(frame) = self.mapper.push(event.type_, event.code, event.value) {
+                self.output.emit(&frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_events(file: &mut File, events: &mut [input_event]) -> io::Result<usize> {
+    // SAFETY: `input_event` is a plain C struct, any bytes are valid
+    let buf =
+        unsafe { slice::from_raw_parts_mut(events.as_mut_ptr().cast::<u8>(), size_of_val(events)) };
+    // evdev always returns whole events
+    Ok(file.read(buf)? / size_of::<input_event>())
+}
+
+/// yield once per read, pending while the keyboard is not grabbed
+impl Stream for KeyRemap {
+    type Item = io::Result<()>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(device) = &mut self.device else {
+            return Poll::Pending;
+        };
+        let mut guard = match ready!(device.poll_read_ready_mut(cx)) {
+            Ok(guard) => guard,
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        };
+        // SAFETY: plain C struct, all zero is valid
+        let mut events = [unsafe { std::mem::zeroed::<input_event>() }; 64];
+        let res = guard.try_io(|device| read_events(device.get_mut(), &mut events));
+        drop(guard);
+        let Ok(res) = res else {
+            return self.poll_next(cx);
+        };
+        let res = match res {
+            Ok(n) => self.forward(&events[..n]),
+            Err(err) => {
+                // e.g. the keyboard is removed
+                self.device = None;
+                Err(err)
+            }
+        };
+        Poll::Ready(Some(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_A: u16 = 30;
+    const KEY_B: u16 = 48;
+    const KEY_C: u16 = 46;
+    const MSC_SCAN: (u16, u16) = (4, 4);
+
+    fn key(mapper: &mut KeyMapper, code: u16, value: i32) -> Option<Vec<(u16, u16, i32)>> {
+        assert_eq!(mapper.push(MSC_SCAN.0, MSC_SCAN.1, 0x70004), None);
+        assert_eq!(mapper.push(EV_KEY, code, value), None);
+        mapper.push(EV_SYN, SYN_REPORT, 0)
+    }
+
+    #[test]
+    fn map_keys() {
+        let mut mapper = KeyMapper {
+            table: BTreeMap::from([(KEY_A, KEY_B)]),
+            ..Default::default()
+        };
+        assert_eq!(key(&mut mapper, KEY_A, 1), Some(vec![(EV_KEY, KEY_B, 1)]));
+        assert_eq!(key(&mut mapper, KEY_A, 2), Some(vec![(EV_KEY, KEY_B, 2)]));
+        assert_eq!(key(&mut mapper, KEY_A, 0), Some(vec![(EV_KEY, KEY_B, 0)]));
+        // not in the table
+        assert_eq!(key(&mut mapper, KEY_C, 1), Some(vec![(EV_KEY, KEY_C, 1)]));
+        assert_eq!(key(&mut mapper, KEY_C, 0), Some(vec![(EV_KEY, KEY_C, 0)]));
+        // nothing to emit
+        assert_eq!(mapper.push(MSC_SCAN.0, MSC_SCAN.1, 0x70004), None);
+        assert_eq!(mapper.push(EV_SYN, SYN_REPORT, 0), None);
+        assert!(mapper.pressed.is_empty());
+    }
+
+    #[test]
+    fn held_across_mode_switch() {
+        let mut mapper = KeyMapper {
+            table: BTreeMap::from([(KEY_A, KEY_B)]),
+            ..Default::default()
+        };
+        assert_eq!(key(&mut mapper, KEY_A, 1), Some(vec![(EV_KEY, KEY_B, 1)]));
+
+        // another mode maps it to other key, the pressed key is still released
+        mapper.table = BTreeMap::from([(KEY_A, KEY_C)]);
+        assert_eq!(key(&mut mapper, KEY_A, 2), Some(vec![(EV_KEY, KEY_B, 2)]));
+        assert_eq!(key(&mut mapper, KEY_A, 0), Some(vec![(EV_KEY, KEY_B, 0)]));
+        assert_eq!(key(&mut mapper, KEY_A, 1), Some(vec![(EV_KEY, KEY_C, 1)]));
+
+        // released when the keyboard is ungrabbed
+        assert_eq!(mapper.release_all(), [(EV_KEY, KEY_C, 0)]);
+        assert_eq!(mapper.release_all(), []);
+    }
+}
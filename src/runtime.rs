@@ -1,45 +1,129 @@
 use crate::{
-    config::{self, Config, SettingMode, VStr},
-    iio::sensor_proxy::{AccelerometerOrientation, SensorProxyProxy},
-    libinput::{EventListener, new_libinput},
+    binding::{RingTracker, Trigger},
+    calibration::{Calibration, Matrix, Region, RotationMode},
+    config::{
+        self, AccelerometerSource, Config, DeviceAccess, KeyboardState, LidState, SettingMode,
+        VStr, VirtualSwitch,
+    },
+    gesture::{Gesture, GestureDevice, TouchRecognizer, TouchpadTracker},
+    iio::{
+        accel::{self, IioAccelerometer},
+        client::{SensorClient, SensorEvent},
+        hinge::{Hinge, HingeListener, Threshold},
+        sensor_proxy::{AccelerometerOrientation, AccelerometerTilt},
+        sysfs::IIO_DEVICES,
+    },
+    libinput::{EventListener, Interface, is_external_keyboard, new_libinput, switch_state},
+    logind::{ActiveSession, DeviceClient, SessionTracker, SleepMonitor},
+    mode::{InputState, ModeRules},
+    orientation::{DeferredRotation, OrientationPolicy},
+    remap::KeyRemap,
+    uinput::{EV_SW, SW_LID, SW_TABLET_MODE, VirtualDevice},
+    user::User,
+    util::{changed_some, next_some},
 };
 use anyhow::{Context, Result, anyhow};
 use futures::StreamExt;
 use input::{
-    Device, Event,
+    Device, Event, SendEventsMode,
     event::{
-        SwitchEvent,
+        DeviceEvent, EventTrait, GestureEvent, KeyboardEvent, SwitchEvent, TabletPadEvent,
+        TabletToolEvent, TouchEvent,
+        gesture::{
+            GestureEndEvent, GestureEventCoordinates, GestureEventTrait, GestureHoldEvent,
+            GesturePinchEvent, GesturePinchEventTrait, GestureSwipeEvent,
+        },
+        keyboard::{KeyState, KeyboardEventTrait},
+        pointer::ButtonState,
         switch::{Switch, SwitchState},
+        tablet_tool::{ProximityState, TipState},
+        touch::{TouchEventPosition, TouchEventSlot, TouchEventTrait},
     },
 };
 use std::{
-    collections::btree_map::{BTreeMap, Entry},
+    collections::{
+        BTreeSet, VecDeque,
+        btree_map::{BTreeMap, Entry},
+    },
+    future::pending,
+    io,
     num::NonZeroUsize,
+    os::unix::process::ExitStatusExt,
+    path::Path,
+    process::{Output, Stdio},
+    time::Duration,
 };
 use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
     process::Command,
     select,
+    signal::unix::{SignalKind, signal},
     sync::{mpsc, watch},
+    time,
 };
 use zbus::Connection;
 
-const DEFAULT_ROTATION: [f32; 6] = [1., 0., 0., 0., 1., 0.];
-const ROTATE_90: [f32; 6] = [0., -1., 1., 1., 0., 0.];
-const ROTATE_180: [f32; 6] = [-1., 0., 1., 0., -1., 1.];
-const ROTATE_270: [f32; 6] = [0., 1., 0., -1., 0., 1.];
+/// variables set by runtime, resolved when the task is running
+const RUNTIME_VARIABLES: [&str; 4] = ["lid", "mode", "hinge-angle", "tilt"];
 
 #[derive(Debug)]
 pub struct Runtime {
-    on_mode_laptop: Option<ActionId>,
-    on_mode_tablet: Option<ActionId>,
-    on_rotate_normal: Option<ActionId>,
-    on_rotate_left_up: Option<ActionId>,
-    on_rotate_right_up: Option<ActionId>,
-    on_rotate_bottom_up: Option<ActionId>,
+    on_mode: BTreeMap<String, ActionId>,
+    on_lid_open: Option<ActionId>,
+    on_lid_close: Option<ActionId>,
+    on_pen_in: Option<ActionId>,
+    on_pen_out: Option<ActionId>,
+    on_rotate_normal: ActionId,
+    on_rotate_left_up: ActionId,
+    on_rotate_right_up: ActionId,
+    on_rotate_bottom_up: ActionId,
+    on_rotate_undefined: Option<ActionId>,
+    on_tilt: BTreeMap<String, ActionId>,
+    on_suspend: Option<ActionId>,
+    on_resume: Option<ActionId>,
     actions: Vec<Action>,
     event: EventListener,
+    /// switch and lid, read again after resume
+    switch_paths: Vec<String>,
+    /// all devices of the seat, for external keyboard hotplug and bindings
+    seat_event: Option<EventListener>,
+    keyboards: BTreeSet<String>,
+    bindings: Vec<Binding>,
+    rings: RingTracker,
+    touchpad: TouchpadTracker,
+    touches: TouchRecognizer,
+    /// seat devices are not calibrated, rotate touch position for gestures
+    touch_rotation: Matrix,
+    orientation_policy: OrientationPolicy,
+    /// `None` if use iio-sensor-proxy
+    iio_accelerometer: Option<IioAccelerometer>,
+    hinge: Option<HingeListener>,
+    on_hinge: Vec<HingeAction>,
+    touchscreen_path: Option<String>,
     touchscreen: Option<Device>,
-    default_mode: SettingMode,
+    /// last calibration, applied again when the touchscreen is reopened
+    calibration: Calibration,
+    /// set by `touchscreen` task
+    touchscreen_enabled: bool,
+    palm_rejection: bool,
+    pen_in: bool,
+    /// active touch slots
+    contacts: BTreeSet<u32>,
+    pen_down: bool,
+    deferred: DeferredRotation,
+    base_calibration: Option<Matrix>,
+    virtual_switch: Option<VirtualDevice>,
+    virtual_switches: Vec<VirtualSwitch>,
+    tablet_modes: Vec<String>,
+    key_remap: Option<KeyRemap>,
+    interface: Interface,
+    /// whether the logind session is active, `None` if open device directly
+    session_active: Option<watch::Receiver<bool>>,
+    /// user name, uid or `active-session`
+    run_as: Option<String>,
+    modes: ModeRules,
+    state: InputState,
+    mode: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,78 +143,648 @@ impl From<usize> for ActionId {
 
 type Action = Vec<Task>;
 
+#[derive(Debug)]
+struct Binding {
+    trigger: Trigger,
+    /// `None` if active in all modes
+    mode: Option<String>,
+    action: ActionId,
+}
+
+/// `on-hinge-above`/`on-hinge-below` with the same angle
+#[derive(Debug)]
+struct HingeAction {
+    threshold: Threshold,
+    above: Option<ActionId>,
+    below: Option<ActionId>,
+}
+
 #[derive(Debug)]
 enum Task {
     Action(ActionId),
-    Cmd(Vec<String>),
-    RotateCalibration(RotationMode),
-    Rotation(bool),
+    Cmd(Cmd),
+    /// store the trimmed stdout to the variable
+    Capture(String, Cmd),
+    /// `None` is follow the `on-rotate-*` actions
+    RotateCalibration(Option<RotationMode>),
+    /// prepended to `on-rotate-*` actions
+    FollowRotation(RotationMode),
+    MapToOutput(Option<Region>),
+    /// `None` to toggle
+    Rotation(Option<bool>),
+    Touchscreen(bool),
+    /// `None` to disable
+    DeferRotation(Option<Duration>),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum RotationMode {
-    Normal,
-    Rotate90,
-    Rotate180,
-    Rotate270,
+#[derive(Debug)]
+struct Cmd {
+    /// the action name, tagged to the output in log
+    tag: String,
+    args: Vec<Arg>,
+    env: Vec<(String, Arg)>,
+    cwd: Option<Arg>,
+    timeout: Option<Duration>,
+    detach: bool,
+    stdin: Option<Arg>,
+}
+
+#[derive(Debug)]
+enum Arg {
+    Value(String),
+    Variable(String),
+}
+
+impl Arg {
+    fn resolve(&self, variables: &BTreeMap<String, String>) -> String {
+        match self {
+            Arg::Value(v) => v.clone(),
+            Arg::Variable(name) => variables.get(name).cloned().unwrap_or_default(),
+        }
+    }
 }
 
 impl Runtime {
-    pub fn new(con: Config) -> Result<Self> {
-        let mut helper = RuntimeHelper::new(con);
-        let on_mode_laptop = helper.get_action("on-mode-laptop").transpose()?;
-        let on_mode_tablet = helper.get_action("on-mode-tablet").transpose()?;
-        let on_rotate_normal = helper.get_action("on-rotate-normal").transpose()?;
-        let on_rotate_left_up = helper.get_action("on-rotate-left-up").transpose()?;
-        let on_rotate_right_up = helper.get_action("on-rotate-right-up").transpose()?;
-        let on_rotate_bottom_up = helper.get_action("on-rotate-bottom-up").transpose()?;
-        let touchscreen = helper
+    pub fn new(mut con: Config) -> Result<Self> {
+        let modes = ModeRules::new(
+            con.modes
+                .drain(..)
+                .flat_map(|x| x.modes)
+                .collect::<Vec<_>>(),
+        )?;
+        let bindings = con
+            .bindings
+            .drain(..)
+            .flat_map(|x| x.bindings)
+            .collect::<Vec<_>>();
+        let remaps = std::mem::take(&mut con.remap);
+        let mut helper = RuntimeHelper::new(con)?;
+        let run_as = helper.settings.run_as.take();
+        // SAFETY: no argument
+        if run_as.is_some() && unsafe { libc::geteuid() } != 0 {
+            return Err(anyhow!(
+                "Require root for run-as, or remove it to run commands as rota"
+            ));
+        }
+        let hinge_angles = helper
+            .actions
+            .keys()
+            .filter_map(|name| {
+                name.strip_prefix("on-hinge-above ")
+                    .or_else(|| name.strip_prefix("on-hinge-below "))?
+                    .parse::<u32>()
+                    .ok()
+            })
+            .collect::<BTreeSet<_>>();
+        let offset = helper
             .settings
-            .touchscreen
-            .map(|path| new_libinput().path_add_device(path.as_str()))
-            .flatten();
-        let mut event = EventListener::new()?;
-        event
-            .path_add_device(&helper.settings.switch)
-            .context("Cannot add switch")?;
+            .calibration_offset
+            .as_deref()
+            .map(str::parse::<RotationMode>)
+            .transpose()?
+            .unwrap_or(RotationMode::Normal);
+        let on_rotate_normal =
+            helper.get_rotate_action("on-rotate-normal", RotationMode::Normal.offset_by(offset))?;
+        let on_rotate_left_up = helper.get_rotate_action(
+            "on-rotate-left-up",
+            RotationMode::Rotate90.offset_by(offset),
+        )?;
+        let on_rotate_right_up = helper.get_rotate_action(
+            "on-rotate-right-up",
+            RotationMode::Rotate270.offset_by(offset),
+        )?;
+        let on_rotate_bottom_up = helper.get_rotate_action(
+            "on-rotate-bottom-up",
+            RotationMode::Rotate180.offset_by(offset),
+        )?;
+        let on_mode = modes
+            .names()
+            .filter_map(|name| {
+                helper
+                    .get_action(format!("on-mode-{name}"))
+                    .map(|id| Ok((name.to_owned(), id?)))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let on_lid_open = helper.get_action("on-lid-open").transpose()?;
+        let on_lid_close = helper.get_action("on-lid-close").transpose()?;
+        let on_pen_in = helper.get_action("on-pen-in").transpose()?;
+        let on_pen_out = helper.get_action("on-pen-out").transpose()?;
+        let on_rotate_undefined = helper.get_action("on-rotate-undefined").transpose()?;
+        let on_suspend = helper.get_action("on-suspend").transpose()?;
+        let on_resume = helper.get_action("on-resume").transpose()?;
+        let on_tilt = AccelerometerTilt::ALL
+            .iter()
+            .filter_map(|tilt| {
+                helper
+                    .get_action(format!("on-tilt-{}", tilt.as_str()))
+                    .map(|id| Ok((tilt.as_str().to_owned(), id?)))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let on_hinge = hinge_angles
+            .into_iter()
+            .map(|angle| {
+                Ok(HingeAction {
+                    threshold: Threshold::new(angle as f64),
+                    above: helper
+                        .get_action(format!("on-hinge-above {angle}"))
+                        .transpose()?,
+                    below: helper
+                        .get_action(format!("on-hinge-below {angle}"))
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let bindings = bindings
+            .into_iter()
+            .map(|binding| {
+                let (trigger, mode, tasks) = Trigger::from_config(binding)?;
+                if let Some(mode) = &mode {
+                    if !modes.names().any(|name| name == mode) {
+                        return Err(anyhow!("Unknown mode for binding: {mode}"));
+                    }
+                }
+                Ok(Binding {
+                    trigger,
+                    mode,
+                    action: helper.add_action("bindings", tasks)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let base_calibration = helper
+            .settings
+            .base_calibration
+            .as_deref()
+            .map(|matrix| {
+                <[f32; 6]>::try_from(matrix)
+                    .map(Matrix)
+                    .map_err(|_| anyhow!("base-calibration should had 6 values"))
+            })
+            .transpose()?;
+        let (interface, session_active) = match helper.settings.device_access {
+            Some(DeviceAccess::Logind) => {
+                let (client, active) =
+                    DeviceClient::spawn().context("Cannot take control of logind session")?;
+                (Interface::Logind(client), Some(active))
+            }
+            Some(DeviceAccess::Direct) | None => (Interface::Direct, None),
+        };
+        let touchscreen_path = helper.settings.touchscreen.take();
+        let touchscreen = touchscreen_path
+            .as_deref()
+            .and_then(|path| new_libinput(interface.clone()).path_add_device(path));
+        let mut event = EventListener::new(interface.clone())?;
+        if let Some(switch) = &helper.settings.switch {
+            event.path_add_device(switch).context("Cannot add switch")?;
+        }
+        if let Some(lid) = &helper.settings.lid {
+            event
+                .path_add_device(lid)
+                .context("Cannot add lid switch")?;
+        }
+        let switch_paths = [&helper.settings.switch, &helper.settings.lid]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect();
+        if let Some(pen) = &helper.settings.pen {
+            event.path_add_device(pen).context("Cannot add pen")?;
+        }
+        let orientation_policy = OrientationPolicy::new(
+            helper.settings.orientation_map.take(),
+            helper.settings.allowed_orientation.take(),
+        )?;
+        let iio_accelerometer = match helper.settings.accelerometer {
+            Some(AccelerometerSource::Iio) => {
+                let device = IioAccelerometer::find(Path::new(IIO_DEVICES))?
+                    .context("Cannot find iio accelerometer")?;
+                log::info!("Use iio accelerometer: {}", device.path().display());
+                let period =
+                    Duration::from_millis(helper.settings.accelerometer_interval.unwrap_or(500));
+                let threshold = helper
+                    .settings
+                    .accelerometer_threshold
+                    .unwrap_or(accel::DEFAULT_THRESHOLD);
+                Some(IioAccelerometer::new(device, period, threshold))
+            }
+            Some(AccelerometerSource::SensorProxy) | None => None,
+        };
+        let hinge = match helper.settings.hinge {
+            Some(source) => {
+                let hinge = Hinge::find(Path::new(IIO_DEVICES), source)?
+                    .context("Cannot find hinge sensor")?;
+                log::info!("Use hinge sensor: {:?}", hinge);
+                let period = Duration::from_millis(helper.settings.hinge_interval.unwrap_or(500));
+                Some(HingeListener::new(hinge, period))
+            }
+            None if !on_hinge.is_empty() => {
+                return Err(anyhow!("Require hinge setting for on-hinge-*"));
+            }
+            None => None,
+        };
+        let defer_rotation = helper
+            .runtime_actions
+            .iter()
+            .flatten()
+            .any(|task| matches!(task, Task::DeferRotation(Some(_))));
+        let virtual_switches = helper.settings.virtual_switch.take().unwrap_or_default();
+        let virtual_switch = match virtual_switches.is_empty() {
+            true => None,
+            false => {
+                let codes = virtual_switches
+                    .iter()
+                    .map(|switch| match switch {
+                        VirtualSwitch::TabletMode => (EV_SW, SW_TABLET_MODE),
+                        VirtualSwitch::Lid => (EV_SW, SW_LID),
+                    })
+                    .collect::<Vec<_>>();
+                Some(
+                    VirtualDevice::new("rota virtual switch", &codes)
+                        .context("Cannot create virtual switch")?,
+                )
+            }
+        };
+        let tablet_modes = helper
+            .settings
+            .tablet_modes
+            .take()
+            .unwrap_or_else(|| vec!["tablet".to_owned()]);
+        let key_remap = match (helper.settings.remap_keyboard.take(), remaps.is_empty()) {
+            (_, true) => None,
+            (Some(keyboard), false) => Some(KeyRemap::new(keyboard, remaps)?),
+            (None, false) => return Err(anyhow!("Require remap-keyboard setting for remap")),
+        };
+        let run_as = helper.settings.run_as.take();
+        // SAFETY: no argument
+        if run_as.is_some() && unsafe { libc::geteuid() } != 0 {
+            return Err(anyhow!(
+                "Require root for run-as, or remove it to run commands as rota"
+            ));
+        }
+        let seat_event = match modes.use_keyboard() || !bindings.is_empty() || defer_rotation {
+            true => Some(EventListener::new_udev("seat0", interface.clone())?),
+            false => None,
+        };
 
         Ok(Self {
-            on_mode_laptop,
-            on_mode_tablet,
+            on_mode,
+            on_lid_open,
+            on_lid_close,
+            on_pen_in,
+            on_pen_out,
             on_rotate_normal,
             on_rotate_left_up,
             on_rotate_right_up,
             on_rotate_bottom_up,
+            on_rotate_undefined,
+            on_tilt,
+            on_suspend,
+            on_resume,
             event,
+            switch_paths,
+            seat_event,
+            keyboards: BTreeSet::new(),
+            bindings,
+            rings: RingTracker::default(),
+            touchpad: TouchpadTracker::default(),
+            touches: TouchRecognizer::default(),
+            touch_rotation: Matrix::IDENTITY,
+            orientation_policy,
+            iio_accelerometer,
+            hinge,
+            on_hinge,
+            touchscreen_path,
             touchscreen,
+            calibration: Calibration::default(),
+            touchscreen_enabled: true,
+            palm_rejection: helper.settings.palm_rejection.unwrap_or(false),
+            pen_in: false,
+            contacts: BTreeSet::new(),
+            pen_down: false,
+            deferred: DeferredRotation::default(),
+            base_calibration,
+            virtual_switch,
+            virtual_switches,
+            tablet_modes,
+            key_remap,
+            interface,
+            session_active,
+            run_as,
+            modes,
+            state: InputState::new(helper.settings.default_mode),
+            mode: String::new(),
             actions: helper.runtime_actions,
-            default_mode: helper.settings.default_mode,
         })
     }
+
+    /// derive mode from current input state, run `on-mode-*` if mode changed
+    fn update_mode(&mut self, action_rt: &mut ActionRuntime) -> Result<()> {
+        let mode = self.modes.derive(&self.state);
+        if mode == self.mode {
+            return self.update_virtual_switch();
+        }
+        log::info!("Enter {mode} mode");
+        self.mode = mode.to_owned();
+        action_rt
+            .variables
+            .insert("mode".to_owned(), self.mode.clone());
+        self.update_virtual_switch()?;
+        if let Some(key_remap) = &mut self.key_remap {
+            // e.g. the keyboard is unplugged or grabbed by another program
+            if let Err(err) = key_remap.set_mode(&self.mode) {
+                log::warn!("Cannot remap keys: {err:#}");
+            }
+        }
+        if let Some(id) = self.on_mode.get(&self.mode) {
+            action_rt.action.send(*id)?;
+        }
+        Ok(())
+    }
+
+    /// run `on-lid-*`, the mode is not updated
+    fn update_lid(&mut self, lid: LidState, action_rt: &mut ActionRuntime) -> Result<()> {
+        let (value, id) = match lid {
+            LidState::Closed => {
+                log::info!("Lid closed");
+                ("closed", self.on_lid_close)
+            }
+            LidState::Open => {
+                log::info!("Lid opened");
+                ("open", self.on_lid_open)
+            }
+        };
+        self.state.lid = lid;
+        action_rt
+            .variables
+            .insert("lid".to_owned(), value.to_owned());
+        id.map(|id| action_rt.action.send(id)).transpose()?;
+        Ok(())
+    }
+
+    /// the switches may be toggled while asleep, libinput only reports the later toggles
+    fn sync_switches(&mut self, action_rt: &mut ActionRuntime) -> Result<()> {
+        for path in self.switch_paths.clone() {
+            let switches = match switch_state(&mut self.interface, Path::new(&path)) {
+                Ok(switches) => switches,
+                Err(err) => {
+                    log::warn!("Cannot read switch {path}: {err}");
+                    continue;
+                }
+            };
+            for (code, on) in switches {
+                match code {
+                    SW_TABLET_MODE => {
+                        log::info!("Tablet switch is {}", if on { "on" } else { "off" });
+                        self.state.switch = match on {
+                            true => SettingMode::Tablet,
+                            false => SettingMode::Laptop,
+                        };
+                    }
+                    SW_LID => {
+                        let lid = match on {
+                            true => LidState::Closed,
+                            false => LidState::Open,
+                        };
+                        if lid != self.state.lid {
+                            self.update_lid(lid, action_rt)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.update_mode(action_rt)
+    }
+
+    /// reflect current mode and lid state, kernel drops the unchanged value
+    fn update_virtual_switch(&mut self) -> Result<()> {
+        let Some(device) = &mut self.virtual_switch else {
+            return Ok(());
+        };
+        let events = self
+            .virtual_switches
+            .iter()
+            .map(|switch| match switch {
+                VirtualSwitch::TabletMode => (
+                    EV_SW,
+                    SW_TABLET_MODE,
+                    self.tablet_modes.contains(&self.mode) as i32,
+                ),
+                VirtualSwitch::Lid => (EV_SW, SW_LID, (self.state.lid == LidState::Closed) as i32),
+            })
+            .collect::<Vec<_>>();
+        device.emit(&events).context("Cannot write virtual switch")
+    }
+
+    /// run `on-rotate-*` for the new orientation
+    fn update_orientation(
+        &mut self,
+        orientation: AccelerometerOrientation,
+        action_rt: &mut ActionRuntime,
+    ) -> Result<()> {
+        let orientation = self.orientation_policy.remap(orientation);
+        // mode rules still see the disallowed orientation
+        if !self.orientation_policy.allows(&orientation) {
+            log::info!("Orientation is not allowed, do not rotate");
+            self.state.orientation = Some(orientation);
+            return self.update_mode(action_rt);
+        }
+        let active = !self.contacts.is_empty() || self.pen_down;
+        if action_rt.defer_rotation.borrow().is_some() && self.deferred.should_defer(active) {
+            log::info!("Defer rotation until contacts released");
+            self.deferred.defer(orientation);
+            return Ok(());
+        }
+        self.deferred.clear();
+        self.state.orientation = Some(orientation.clone());
+        self.update_mode(action_rt)?;
+        let id = match orientation {
+            AccelerometerOrientation::Normal => self.on_rotate_normal,
+            AccelerometerOrientation::BottomUp => self.on_rotate_bottom_up,
+            AccelerometerOrientation::LeftUp => self.on_rotate_left_up,
+            AccelerometerOrientation::RightUp => self.on_rotate_right_up,
+            AccelerometerOrientation::Undefined => {
+                log::info!("Undefined rotation");
+                match self.on_rotate_undefined {
+                    Some(id) => id,
+                    None => return Ok(()),
+                }
+            }
+            AccelerometerOrientation::Unknown(value) => {
+                log::error!("Unknown rotation: {value}");
+                return Ok(());
+            }
+        };
+        action_rt.action.send(id)?;
+        Ok(())
+    }
+
+    /// run the bindings of the trigger in current mode
+    fn run_bindings(&self, trigger: Trigger, action_rt: &ActionRuntime) -> Result<()> {
+        for binding in &self.bindings {
+            let in_mode = binding.mode.as_ref().is_none_or(|mode| *mode == self.mode);
+            if binding.trigger.matches(&trigger) && in_mode {
+                log::info!("Binding triggered: {:?}", trigger);
+                action_rt.action.send(binding.action)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// start the grace period of deferred rotation when all contacts released
+    fn update_contacts(&mut self, action_rt: &ActionRuntime) {
+        let active = !self.contacts.is_empty() || self.pen_down;
+        let grace = action_rt.defer_rotation.borrow().unwrap_or_default();
+        self.deferred.update_contacts(active, grace);
+    }
+
+    fn update_calibration(&mut self) -> Result<()> {
+        self.touch_rotation = self.calibration.current_rotation().matrix();
+        let Some(touchscreen) = &mut self.touchscreen else {
+            return Ok(());
+        };
+        let normal = self.base_calibration.unwrap_or_else(|| {
+            touchscreen
+                .config_calibration_default_matrix()
+                .map(Matrix)
+                .unwrap_or_default()
+        });
+        let Matrix(matrix) = self.calibration.matrix(normal);
+        log::info!("Set calibration to: {:?}", matrix);
+        touchscreen
+            .config_calibration_set_matrix(matrix)
+            .map_err(|err| anyhow!("Set calibration matrix error: {:?}", err))
+    }
+
+    /// devices are revoked by logind while the session is inactive,
+    /// and reopened with new fds once all of them are resumed
+    fn update_session_active(&mut self, active: bool) -> Result<()> {
+        // also when the pause is missed, the old fds are revoked
+        let contexts = [Some(&mut self.event), self.seat_event.as_mut()];
+        for event in contexts.into_iter().flatten() {
+            event.suspend();
+        }
+        self.touchscreen = None;
+        if !active {
+            log::info!("Session is inactive, suspend devices");
+            return Ok(());
+        }
+
+        log::info!("Session is active, resume devices");
+        let contexts = [Some(&mut self.event), self.seat_event.as_mut()];
+        for event in contexts.into_iter().flatten() {
+            if event.resume().is_err() {
+                log::warn!("Cannot resume libinput devices");
+            }
+        }
+        // the device of a path context is removed on suspend
+        self.touchscreen = self
+            .touchscreen_path
+            .as_deref()
+            .and_then(|path| new_libinput(self.interface.clone()).path_add_device(path));
+        self.update_calibration()?;
+        self.update_send_events()
+    }
+
+    /// disable touchscreen by `touchscreen` task or palm rejection
+    fn update_send_events(&mut self) -> Result<()> {
+        let enable = self.touchscreen_enabled && !(self.palm_rejection && self.pen_in);
+        let Some(touchscreen) = &mut self.touchscreen else {
+            return Ok(());
+        };
+        let mode = match enable {
+            true => SendEventsMode::ENABLED,
+            false => SendEventsMode::DISABLED,
+        };
+        log::info!("Set touchscreen send events to: {:?}", mode);
+        touchscreen
+            .config_send_events_set_mode(mode)
+            .map_err(|err| anyhow!("Set touchscreen send events error: {:?}", err))
+    }
+
+    /// normalized position in the current rotation
+    fn touch_position(&self, event: &impl TouchEventPosition) -> (f64, f64) {
+        let position = (event.x_transformed(1) as f32, event.y_transformed(1) as f32);
+        let (x, y) = self.touch_rotation.apply(position);
+        (x as f64, y as f64)
+    }
+
+    fn run_gesture(
+        &self,
+        gesture: Gesture,
+        fingers: u32,
+        device: GestureDevice,
+        action_rt: &ActionRuntime,
+    ) -> Result<()> {
+        let trigger = Trigger::Gesture {
+            gesture,
+            fingers: Some(fingers),
+            device: Some(device),
+        };
+        self.run_bindings(trigger, action_rt)
+    }
+
     pub async fn run(mut self) -> Result<()> {
         let (rotation, mut rotation_r) = watch::channel(false);
-        let (rotation_calibration, mut rotation_calibration_r) =
-            watch::channel(RotationMode::Normal);
+        let (calibration, mut calibration_r) = watch::channel(Calibration::default());
+        let (touchscreen, mut touchscreen_r) = watch::channel(true);
+        let defer_rotation = watch::Sender::new(None);
+        let mut defer_rotation_r = defer_rotation.subscribe();
         let (action, mut action_r) = mpsc::unbounded_channel();
 
-        let action_rt = ActionRuntime {
+        let mut action_rt = ActionRuntime {
             action: action.clone(),
             rotation,
-            rotation_calibration,
+            calibration,
+            touchscreen,
+            defer_rotation,
+            run_as: match self.run_as.take() {
+                Some(user) if user == "active-session" => Some(RunAs::ActiveSession),
+                Some(user) => Some(RunAs::User(
+                    User::new(&user).with_context(|| format!("Cannot find user: {user}"))?,
+                )),
+                None => None,
+            },
+            session: None,
+            variables: BTreeMap::from([("lid".to_owned(), "open".to_owned())]),
         };
 
-        match self.default_mode {
-            SettingMode::Laptop => self.on_mode_laptop.map(|id| action.send(id)).transpose(),
-            SettingMode::Tablet => self.on_mode_tablet.map(|id| action.send(id)).transpose(),
-        }?;
+        // the environment of commands, required to find the user of `active-session`
+        let sessions = async { SessionTracker::new(Connection::system().await?).await }.await;
+        let mut sessions = match (sessions, &action_rt.run_as) {
+            (Ok(sessions), _) => Some(sessions),
+            (Err(err), Some(RunAs::ActiveSession)) => {
+                return Err(err).context("Cannot follow active session");
+            }
+            (Err(err), _) => {
+                log::warn!("Cannot follow active session: {err}");
+                None
+            }
+        };
+        if let Some(sessions) = &sessions {
+            action_rt.update_session(sessions).await;
+        }
 
-        let conn = Connection::system().await?;
-        let proxy = SensorProxyProxy::new(&conn).await?;
-        let mut accelerometer = proxy.receive_accelerometer_orientation_changed().await;
+        // best-effort, only for `on-suspend`/`on-resume` and resync after resume
+        let mut sleep = match Connection::system().await {
+            Ok(conn) => SleepMonitor::new(&conn)
+                .await
+                .inspect_err(|err| log::warn!("Cannot monitor system sleep: {err}"))
+                .ok(),
+            Err(err) => {
+                log::warn!("Cannot monitor system sleep: {err}");
+                None
+            }
+        };
 
-        // always have the init vaule
-        // we don't need that
-        let _ = accelerometer.next().await;
+        self.update_mode(&mut action_rt)?;
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigint = signal(SignalKind::interrupt())?;
+
+        let mut sensor = match self.iio_accelerometer {
+            Some(_) => None,
+            None => Some(SensorClient::new(Connection::system().await?).await?),
+        };
 
         loop {
             select! {
@@ -150,34 +804,49 @@ impl Runtime {
                     match *rotation_r.borrow() {
                         true => {
                             log::info!("Enable rotation");
-                            proxy.claim_accelerometer().await?;
+                            if let Some(sensor) = &mut sensor {
+                                // claimed again on reconnect
+                                if let Err(err) = sensor.claim_accelerometer().await {
+                                    log::warn!("Cannot claim accelerometer: {err}");
+                                }
+                            }
+                            if let Some(accelerometer) = &mut self.iio_accelerometer {
+                                accelerometer.claim();
+                            }
                         }
                         false => {
                             log::info!("Disable rotation");
-                            proxy.release_accelerometer().await?
+                            self.deferred.clear();
+                            if let Some(sensor) = &mut sensor {
+                                if let Err(err) = sensor.release_accelerometer().await {
+                                    log::warn!("Cannot release accelerometer: {err}");
+                                }
+                            }
+                            if let Some(accelerometer) = &mut self.iio_accelerometer {
+                                accelerometer.release();
+                            }
                         }
                     }
                 }
 
-                res = rotation_calibration_r.changed() => {
+                res = calibration_r.changed() => {
                     res?;
 
-                    let calibration = rotation_calibration_r.borrow();
-                    let Some(touchscreen) = &mut self.touchscreen else {
-                        continue;
-                    };
-                    let normal = touchscreen
-                        .config_calibration_default_matrix()
-                        .unwrap_or(DEFAULT_ROTATION);
-                    let matrix = match *calibration {
-                        RotationMode::Normal => normal,
-                        RotationMode::Rotate90 => calibration_matrix_product(normal, ROTATE_90),
-                        RotationMode::Rotate180 => calibration_matrix_product(normal, ROTATE_180),
-                        RotationMode::Rotate270 => calibration_matrix_product(normal, ROTATE_270),
-                    };
-                    log::info!("Set calibration to: {:?}", matrix);
-                    touchscreen.config_calibration_set_matrix(matrix)
-                        .map_err(|err| anyhow!("Set calibration matrix error: {:?}", err))?;
+                    self.calibration = *calibration_r.borrow();
+                    self.update_calibration()?;
+                }
+
+                // logind session switch
+                active = changed_some(&mut self.session_active) => {
+                    let active = active.context("Logind session is closed")?;
+                    self.update_session_active(active)?;
+                }
+
+                res = touchscreen_r.changed() => {
+                    res?;
+
+                    self.touchscreen_enabled = *touchscreen_r.borrow();
+                    self.update_send_events()?;
                 }
 
                 // libinput event
@@ -187,54 +856,368 @@ impl Runtime {
                         Event::Device(dev) => log::info!("Device event: {:?}", dev),
 
                         Event::Switch(SwitchEvent::Toggle(event)) => {
-                            if Some(Switch::TabletMode) != event.switch() {
-                                log::info!("Get non-tablet switch event, discard");
-                                continue;
+                            match (event.switch(), event.switch_state()) {
+                                (Some(Switch::TabletMode), SwitchState::On) => {
+                                    log::info!("Tablet switch on");
+                                    self.state.switch = SettingMode::Tablet;
+                                }
+                                (Some(Switch::TabletMode), SwitchState::Off) => {
+                                    log::info!("Tablet switch off");
+                                    self.state.switch = SettingMode::Laptop;
+                                }
+                                (Some(Switch::Lid), SwitchState::On) => {
+                                    self.update_lid(LidState::Closed, &mut action_rt)?;
+                                }
+                                (Some(Switch::Lid), SwitchState::Off) => {
+                                    self.update_lid(LidState::Open, &mut action_rt)?;
+                                }
+                                _ => {
+                                    log::info!("Get unknown switch event, discard");
+                                    continue;
+                                }
                             }
-                            match event.switch_state() {
-                                SwitchState::On => {
-                                    log::info!("Enter tablet mode");
-                                    self.on_mode_tablet.map(|id| action.send(id)).transpose()?;
+                            self.update_mode(&mut action_rt)?;
+                        }
+
+                        Event::Tablet(TabletToolEvent::Proximity(event)) => {
+                            self.pen_in = event.proximity_state() == ProximityState::In;
+                            let id = match self.pen_in {
+                                true => {
+                                    log::info!("Pen in");
+                                    self.on_pen_in
                                 }
-                                SwitchState::Off => {
-                                    log::info!("Enter laptop mode");
-                                    self.on_mode_laptop.map(|id| action.send(id)).transpose()?;
+                                false => {
+                                    log::info!("Pen out");
+                                    self.on_pen_out
                                 }
+                            };
+                            id.map(|id| action.send(id)).transpose()?;
+                            if self.palm_rejection {
+                                self.update_send_events()?;
                             }
                         }
 
+                        Event::Tablet(TabletToolEvent::Tip(event)) => {
+                            self.pen_down = event.tip_state() == TipState::Down;
+                            self.update_contacts(&action_rt);
+                        }
+
+                        Event::Tablet(_) => {}
+
                         event => log::warn!("Unknown event: {:?}", event),
                     }
                 }
 
-                // accelerometer
-                Some(event) = accelerometer.next() => {
-                    let event = event.get().await?;
-                    match event {
-                        AccelerometerOrientation::Normal => {
-                            self.on_rotate_normal
-                                .map(|id| action.send(id))
-                                .transpose()?;
+                // external keyboard hotplug and bindings
+                Some(event) = next_some(&mut self.seat_event) => {
+                    match event? {
+                        Event::Device(DeviceEvent::Added(event)) => {
+                            let device = event.device();
+                            if is_external_keyboard(&device) {
+                                log::info!("External keyboard added: {}", device.name());
+                                self.keyboards.insert(device.sysname().to_owned());
+                            }
+                        }
+                        Event::Device(DeviceEvent::Removed(event)) => {
+                            self.keyboards.remove(event.device().sysname());
                         }
-                        AccelerometerOrientation::BottomUp => {
-                            self.on_rotate_bottom_up
-                                .map(|id| action.send(id))
-                                .transpose()?;
+                        Event::Keyboard(KeyboardEvent::Key(event)) => {
+                            if event.key_state() == KeyState::Pressed {
+                                self.run_bindings(Trigger::Key(event.key()), &action_rt)?;
+                            }
+                            continue;
+                        }
+                        Event::TabletPad(TabletPadEvent::Button(event)) => {
+                            if event.button_state() == ButtonState::Pressed {
+                                let trigger = Trigger::PadButton(event.button_number());
+                                self.run_bindings(trigger, &action_rt)?;
+                            }
+                            continue;
+                        }
+                        Event::TabletPad(TabletPadEvent::Ring(event)) => {
+                            let ring = event.number();
+                            if let Some(clockwise) = self.rings.update(ring, event.position()) {
+                                self.run_bindings(Trigger::PadRing { ring, clockwise }, &action_rt)?;
+                            }
+                            continue;
+                        }
+                        Event::Gesture(GestureEvent::Swipe(event)) => {
+                            let gesture = match &event {
+                                GestureSwipeEvent::Begin(_) => {
+                                    self.touchpad.begin();
+                                    None
+                                }
+                                GestureSwipeEvent::Update(event) => {
+                                    self.touchpad.swipe_update(event.dx(), event.dy());
+                                    None
+                                }
+                                GestureSwipeEvent::End(event) => {
+                                    self.touchpad.swipe_end(event.cancelled())
+                                }
+                                _ => None,
+                            };
+                            if let Some(gesture) = gesture {
+                                let fingers = event.finger_count() as u32;
+                                self.run_gesture(gesture, fingers, GestureDevice::Touchpad, &action_rt)?;
+                            }
+                            continue;
+                        }
+                        Event::Gesture(GestureEvent::Pinch(event)) => {
+                            let gesture = match &event {
+                                GesturePinchEvent::Begin(_) => {
+                                    self.touchpad.begin();
+                                    None
+                                }
+                                GesturePinchEvent::Update(event) => {
+                                    self.touchpad.pinch_update(event.scale());
+                                    None
+                                }
+                                GesturePinchEvent::End(event) => {
+                                    self.touchpad.pinch_end(event.cancelled())
+                                }
+                                _ => None,
+                            };
+                            if let Some(gesture) = gesture {
+                                let fingers = event.finger_count() as u32;
+                                self.run_gesture(gesture, fingers, GestureDevice::Touchpad, &action_rt)?;
+                            }
+                            continue;
                         }
-                        AccelerometerOrientation::LeftUp => {
-                            self.on_rotate_left_up
-                                .map(|id| action.send(id))
-                                .transpose()?;
+                        Event::Gesture(GestureEvent::Hold(GestureHoldEvent::End(event))) => {
+                            if !event.cancelled() {
+                                let fingers = event.finger_count() as u32;
+                                self.run_gesture(Gesture::Hold, fingers, GestureDevice::Touchpad, &action_rt)?;
+                            }
+                            continue;
                         }
-                        AccelerometerOrientation::RightUp => {
-                            self.on_rotate_right_up
-                                .map(|id| action.send(id))
-                                .transpose()?;
+                        Event::Tablet(TabletToolEvent::Tip(event)) => {
+                            self.pen_down = event.tip_state() == TipState::Down;
+                            self.update_contacts(&action_rt);
+                            continue;
                         }
-                        AccelerometerOrientation::Undefined => log::warn!("Undefined rotation"),
-                        AccelerometerOrientation::Unknown(value) => {
-                            log::error!("Unknown rotation: {value}")
+                        Event::Touch(event) => {
+                            match &event {
+                                TouchEvent::Down(event) => {
+                                    self.contacts.insert(event.seat_slot());
+                                }
+                                TouchEvent::Up(event) => {
+                                    self.contacts.remove(&event.seat_slot());
+                                }
+                                TouchEvent::Cancel(_) => self.contacts.clear(),
+                                _ => {}
+                            }
+                            self.update_contacts(&action_rt);
+                            let recognized = match &event {
+                                TouchEvent::Down(event) => {
+                                    let position = self.touch_position(event);
+                                    self.touches.down(event.seat_slot(), position, event.time_usec());
+                                    None
+                                }
+                                TouchEvent::Motion(event) => {
+                                    let position = self.touch_position(event);
+                                    self.touches.motion(event.seat_slot(), position);
+                                    None
+                                }
+                                TouchEvent::Up(event) => {
+                                    self.touches.up(event.seat_slot(), event.time_usec())
+                                }
+                                TouchEvent::Cancel(_) => {
+                                    self.touches.cancel();
+                                    None
+                                }
+                                _ => None,
+                            };
+                            if let Some((gesture, fingers)) = recognized {
+                                self.run_gesture(gesture, fingers, GestureDevice::Touchscreen, &action_rt)?;
+                            }
+                            continue;
                         }
+                        _ => continue,
+                    }
+                    self.state.keyboard = match self.keyboards.is_empty() {
+                        true => KeyboardState::Absent,
+                        false => KeyboardState::Present,
+                    };
+                    self.update_mode(&mut action_rt)?;
+                }
+
+                // active session switch
+                _ = async {
+                    match &mut sessions {
+                        Some(sessions) => sessions.changed().await,
+                        None => pending().await,
+                    }
+                } => {
+                    if let Some(sessions) = &sessions {
+                        action_rt.update_session(sessions).await;
+                    }
+                }
+
+                // system sleep
+                start = async {
+                    match &mut sleep {
+                        Some(sleep) => sleep.next().await,
+                        None => pending().await,
+                    }
+                } => {
+                    let Some(sleep) = &mut sleep else {
+                        continue;
+                    };
+                    let start = match start {
+                        Ok(start) => start,
+                        Err(err) => {
+                            log::warn!("Cannot read sleep signal: {err}");
+                            continue;
+                        }
+                    };
+                    if start {
+                        log::info!("Prepare for sleep");
+                        // finish before the delay lock is released, include the nested actions
+                        if let Some(id) = self.on_suspend {
+                            action_rt.run_action_inline(&self.actions, id).await?;
+                        }
+                        sleep.release();
+                        continue;
+                    }
+
+                    log::info!("Resume from sleep");
+                    if let Err(err) = sleep.inhibit().await {
+                        log::warn!("Cannot delay system sleep: {err}");
+                    }
+                    self.sync_switches(&mut action_rt)?;
+                    self.update_calibration()?;
+                    if let Some(sensor) = &mut sensor {
+                        match sensor.reclaim_accelerometer().await {
+                            Ok(Some(AccelerometerOrientation::Undefined) | None) => {}
+                            Ok(Some(orientation)) => {
+                                self.update_orientation(orientation, &mut action_rt)?;
+                            }
+                            Err(err) => log::warn!("Cannot claim accelerometer again: {err}"),
+                        }
+                    }
+                    if let Some(accelerometer) = &mut self.iio_accelerometer {
+                        if *rotation_r.borrow() {
+                            // yield the first reading again
+                            accelerometer.claim();
+                        }
+                    }
+                    self.on_resume.map(|id| action.send(id)).transpose()?;
+                }
+
+                // exit, the virtual devices and sensor claims are released on drop
+                _ = sigterm.recv() => {
+                    log::info!("Receive SIGTERM, exit");
+                    return Ok(());
+                }
+                _ = sigint.recv() => {
+                    log::info!("Receive SIGINT, exit");
+                    return Ok(());
+                }
+
+                // remapped keys
+                Some(res) = next_some(&mut self.key_remap) => {
+                    if let Err(err) = res {
+                        log::warn!("Cannot remap keys: {err}");
+                    }
+                }
+
+                // deferred rotation
+                Some(orientation) = self.deferred.expired() => {
+                    self.update_orientation(orientation, &mut action_rt)?;
+                }
+
+                res = defer_rotation_r.changed() => {
+                    res?;
+
+                    if defer_rotation_r.borrow().is_none() {
+                        self.deferred.clear();
+                    }
+                }
+
+                // hinge angle
+                Some(angle) = next_some(&mut self.hinge) => {
+                    let angle = match angle {
+                        Ok(Some(angle)) => angle,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            log::warn!("Cannot read hinge angle: {err}");
+                            continue;
+                        }
+                    };
+                    log::debug!("Hinge angle: {angle}");
+                    self.state.hinge = Some(angle);
+                    action_rt
+                        .variables
+                        .insert("hinge-angle".to_owned(), format!("{angle:.0}"));
+                    for hinge in &mut self.on_hinge {
+                        let id = match hinge.threshold.update(angle) {
+                            Some(true) => hinge.above,
+                            Some(false) => hinge.below,
+                            None => continue,
+                        };
+                        log::info!("Hinge angle {angle:.0} crossed {}", hinge.threshold.angle);
+                        id.map(|id| action.send(id)).transpose()?;
+                    }
+                    self.update_mode(&mut action_rt)?;
+                }
+
+                // accelerometer
+                event = async {
+                    match &mut sensor {
+                        Some(sensor) => sensor.next().await,
+                        None => pending().await,
+                    }
+                } => {
+                    match event? {
+                        SensorEvent::Connected => {
+                            log::info!("Connected to iio-sensor-proxy");
+                            let (tilt, orientation) = match &sensor {
+                                Some(sensor) => (sensor.tilt().await, sensor.orientation().await),
+                                None => (None, None),
+                            };
+                            action_rt.variables.insert(
+                                "tilt".to_owned(),
+                                tilt.as_ref().map(|tilt| tilt.as_str().to_owned()).unwrap_or_default(),
+                            );
+                            self.state.tilt = tilt;
+                            self.update_mode(&mut action_rt)?;
+                            // only changes are reported, the orientation may have changed while it is gone
+                            let orientation = orientation.filter(|orientation| {
+                                *orientation != AccelerometerOrientation::Undefined
+                                    && self.state.orientation
+                                        != Some(self.orientation_policy.remap(orientation.clone()))
+                            });
+                            if let Some(orientation) = orientation {
+                                self.update_orientation(orientation, &mut action_rt)?;
+                            }
+                        }
+                        SensorEvent::Disconnected => {
+                            log::warn!("iio-sensor-proxy is gone, wait for it");
+                            action_rt.variables.remove("tilt");
+                            self.state.tilt = None;
+                            self.update_mode(&mut action_rt)?;
+                        }
+                        SensorEvent::Orientation(event) => {
+                            self.update_orientation(event, &mut action_rt)?;
+                        }
+                        SensorEvent::Tilt(tilt) => {
+                            log::info!("Tilt: {}", tilt.as_str());
+                            action_rt
+                                .variables
+                                .insert("tilt".to_owned(), tilt.as_str().to_owned());
+                            if let Some(id) = self.on_tilt.get(tilt.as_str()) {
+                                action.send(*id)?;
+                            }
+                            self.state.tilt = Some(tilt);
+                            self.update_mode(&mut action_rt)?;
+                        }
+                    }
+                }
+
+                Some(event) = next_some(&mut self.iio_accelerometer) => {
+                    match event {
+                        Ok(event) => self.update_orientation(event, &mut action_rt)?,
+                        Err(err) => log::warn!("Cannot read iio accelerometer: {err}"),
                     }
                 }
 
@@ -244,60 +1227,194 @@ impl Runtime {
     }
 }
 
-/// matrix is look like this
-///
-/// ```text
-/// [0] [1] [2]
-/// [3] [4] [5]
-///  0   0   1
-/// ```
-fn calibration_matrix_product(a: [f32; 6], b: [f32; 6]) -> [f32; 6] {
-    [
-        a[0] * b[0] + a[1] * b[3],
-        a[0] * b[1] + a[1] * b[4],
-        a[0] * b[2] + a[1] * b[5] + a[2],
-        a[3] * b[0] + a[4] * b[3],
-        a[3] * b[1] + a[4] * b[4],
-        a[3] * b[2] + a[4] * b[5] + a[5],
-    ]
+/// log each line of the output with the tag
+fn log_lines(tag: String, level: log::Level, output: impl AsyncRead + Unpin + Send + 'static) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            log::log!(level, "[{tag}] {line}");
+        }
+    });
+}
+
+#[derive(Debug)]
+enum RunAs {
+    User(User),
+    /// the user of the active session on seat0
+    ActiveSession,
 }
 
 struct ActionRuntime {
     action: mpsc::UnboundedSender<ActionId>,
     rotation: watch::Sender<bool>,
-    rotation_calibration: watch::Sender<RotationMode>,
+    calibration: watch::Sender<Calibration>,
+    touchscreen: watch::Sender<bool>,
+    /// grace period, `None` if disabled
+    defer_rotation: watch::Sender<Option<Duration>>,
+    /// `None` to run commands as rota itself
+    run_as: Option<RunAs>,
+    /// the environment of commands run as its user
+    session: Option<ActiveSession>,
+    variables: BTreeMap<String, String>,
 }
 
 impl ActionRuntime {
+    async fn update_session(&mut self, sessions: &SessionTracker) {
+        self.session = sessions
+            .current()
+            .await
+            .inspect_err(|err| log::warn!("Cannot get active session: {err}"))
+            .ok()
+            .flatten();
+        match &self.session {
+            Some(session) => log::info!("Active session: {} (uid {})", session.id, session.uid),
+            None => log::info!("No active session"),
+        }
+    }
+
+    /// run as the `run-as` user, `None` if detached or skipped
+    async fn run_cmd(
+        &self,
+        cmd: &Cmd,
+        variables: &BTreeMap<String, String>,
+        capture: bool,
+    ) -> Result<Option<Output>> {
+        let user = match (&self.run_as, &self.session) {
+            (None, _) => None,
+            (Some(RunAs::User(user)), _) => Some(user.clone()),
+            (Some(RunAs::ActiveSession), Some(session)) => match User::from_uid(session.uid) {
+                Ok(user) => Some(user),
+                Err(err) => {
+                    log::warn!(
+                        "[{}] Skip command, cannot find user {}: {err}",
+                        cmd.tag,
+                        session.uid
+                    );
+                    return Ok(None);
+                }
+            },
+            (Some(RunAs::ActiveSession), None) => {
+                log::warn!("[{}] Skip command, no active session", cmd.tag);
+                return Ok(None);
+            }
+        };
+        // as root, rota itself can reach any session
+        // SAFETY: no argument
+        let uid = user
+            .as_ref()
+            .map_or_else(|| unsafe { libc::geteuid() }, |user| user.uid);
+        let session = self
+            .session
+            .as_ref()
+            .filter(|session| session.uid == uid || uid == 0);
+        spawn(cmd, variables, user.as_ref(), session, capture).await
+    }
+
     async fn run_action(&self, action: &Action) -> Result<()> {
+        // captured variables only live in this action
+        let mut variables = self.variables.clone();
         for task in action {
-            self.run_task(task).await?;
+            self.run_task(task, &mut variables).await?;
         }
         Ok(())
     }
 
-    async fn run_task(&self, task: &Task) -> Result<()> {
+    /// run the nested actions in place instead of queuing them, in the same order
+    async fn run_action_inline(&self, actions: &[Action], id: ActionId) -> Result<()> {
+        let mut queue = VecDeque::from([id]);
+        let mut done = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            // the actions may run each other
+            if done.contains(&id) {
+                continue;
+            }
+            done.push(id);
+            let mut variables = self.variables.clone();
+            for task in &actions[usize::from(id)] {
+                match task {
+                    Task::Action(id) => queue.push_back(*id),
+                    task => self.run_task(task, &mut variables).await?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_task(&self, task: &Task, variables: &mut BTreeMap<String, String>) -> Result<()> {
         log::debug!("Running task: {:?}", task);
         match task {
             Task::Action(id) => self.action.send(*id)?,
-            Task::Cmd(args) => {
-                log::info!("Running command: {:?}", args);
-                let mut cmd =
-                    Command::new(args.first().context("cmd should had at least one args")?);
-                cmd.args(&args[1..]);
-                cmd.spawn()?.wait().await?;
+            Task::Cmd(cmd) => {
+                // a broken command does not stop the daemon
+                if let Err(err) = self.run_cmd(cmd, variables, false).await {
+                    log::warn!("[{}] Cannot run command: {err:#}", cmd.tag);
+                }
+            }
+            Task::Capture(name, cmd) => {
+                // never detached, `None` if skipped
+                let output = self
+                    .run_cmd(cmd, variables, true)
+                    .await
+                    .inspect_err(|err| log::warn!("[{}] Cannot run command: {err:#}", cmd.tag))
+                    .ok()
+                    .flatten();
+                // not the stale value of last run
+                let (stdout, status) = match output {
+                    Some(output) => (
+                        String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+                        output
+                            .status
+                            .code()
+                            .or_else(|| output.status.signal().map(|signal| 128 + signal))
+                            .unwrap_or(-1),
+                    ),
+                    None => (String::new(), -1),
+                };
+                log::debug!("Captured {name}: {stdout:?}, status {status}");
+                variables.insert(name.clone(), stdout);
+                variables.insert(format!("{name}-status"), status.to_string());
             }
             Task::Rotation(enable) => {
                 self.rotation.send_if_modified(|old| {
+                    let new = enable.unwrap_or(!*old);
+                    let change = *old != new;
+                    *old = new;
+                    change
+                });
+            }
+            Task::Touchscreen(enable) => {
+                self.touchscreen.send_if_modified(|old| {
                     let change = old != enable;
                     *old = *enable;
                     change
                 });
             }
+            Task::DeferRotation(grace) => {
+                self.defer_rotation.send_replace(*grace);
+            }
             Task::RotateCalibration(mode) => {
-                self.rotation_calibration.send_if_modified(|old| {
-                    let change = old != mode;
-                    *old = *mode;
+                self.calibration.send_if_modified(|old| {
+                    let new = Calibration {
+                        rotation: mode.unwrap_or(old.rotation),
+                        follow: mode.is_none(),
+                        ..*old
+                    };
+                    let change = *old != new;
+                    *old = new;
+                    change
+                });
+            }
+            Task::FollowRotation(mode) => {
+                self.calibration.send_if_modified(|old| {
+                    let change = old.follow && old.orientation != *mode;
+                    old.orientation = *mode;
+                    change
+                });
+            }
+            Task::MapToOutput(region) => {
+                self.calibration.send_if_modified(|old| {
+                    let change = old.region != *region;
+                    old.region = *region;
                     change
                 });
             }
@@ -306,6 +1423,119 @@ impl ActionRuntime {
     }
 }
 
+/// stdout and stderr are logged line by line unless stdout is captured, `None` if detached
+async fn spawn(
+    cmd: &Cmd,
+    variables: &BTreeMap<String, String>,
+    user: Option<&User>,
+    session: Option<&ActiveSession>,
+    capture: bool,
+) -> Result<Option<Output>> {
+    let args = cmd
+        .args
+        .iter()
+        .map(|arg| arg.resolve(variables))
+        .collect::<Vec<_>>();
+    log::info!("[{}] Running command: {:?}", cmd.tag, args);
+    let mut command = Command::new(args.first().context("cmd should had at least one args")?);
+    if let Some(user) = user {
+        user.apply(&mut command);
+    }
+    if let Some(session) = session {
+        command.envs(session.env());
+    }
+    command
+        .args(&args[1..])
+        .envs(
+            cmd.env
+                .iter()
+                .map(|(name, value)| (name, value.resolve(variables))),
+        )
+        .stdin(match cmd.stdin {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = &cmd.cwd {
+        command.current_dir(cwd.resolve(variables));
+    }
+    if cmd.timeout.is_some() {
+        // kill the whole group on timeout, include the grandchildren
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+
+    if let (Some(input), Some(mut stdin)) = (&cmd.stdin, child.stdin.take()) {
+        let input = input.resolve(variables);
+        // the child may not read it at all
+        tokio::spawn(async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        });
+    }
+    let stdout = match (capture, child.stdout.take()) {
+        (true, Some(mut stdout)) => Some(tokio::spawn(async move {
+            let mut buf = Vec::new();
+            stdout.read_to_end(&mut buf).await.map(|_| buf)
+        })),
+        (false, Some(stdout)) => {
+            log_lines(cmd.tag.clone(), log::Level::Info, stdout);
+            None
+        }
+        (_, None) => None,
+    };
+    if let Some(stderr) = child.stderr.take() {
+        log_lines(cmd.tag.clone(), log::Level::Warn, stderr);
+    }
+
+    let tag = cmd.tag.clone();
+    let timeout = cmd.timeout;
+    let wait = async move {
+        let status = match timeout {
+            Some(duration) => match time::timeout(duration, child.wait()).await {
+                Ok(status) => status?,
+                Err(_) => {
+                    log::warn!("[{tag}] Command timed out, kill it");
+                    if let Some(pid) = child.id() {
+                        // SAFETY: no pointer argument
+                        unsafe { libc::kill(-(pid as i32), libc::SIGKILL) };
+                    }
+                    child.kill().await?;
+                    child.wait().await?
+                }
+            },
+            None => child.wait().await?,
+        };
+        if !status.success() {
+            log::warn!("[{tag}] Command exited with {status}");
+        }
+        Ok::<_, io::Error>(status)
+    };
+    match cmd.detach {
+        true => {
+            let tag = cmd.tag.clone();
+            tokio::spawn(async move {
+                if let Err(err) = wait.await {
+                    log::warn!("[{tag}] Cannot wait command: {err}");
+                }
+            });
+            Ok(None)
+        }
+        false => {
+            let status = wait.await?;
+            let stdout = match stdout {
+                Some(stdout) => stdout.await??,
+                None => Vec::new(),
+            };
+            Ok(Some(Output {
+                status,
+                stdout,
+                stderr: Vec::new(),
+            }))
+        }
+    }
+}
+
 #[derive(Debug)]
 struct RuntimeHelper {
     actions: BTreeMap<String, Vec<config::Task>>,
@@ -313,29 +1543,43 @@ struct RuntimeHelper {
     settings: config::Settings,
     action_id_map: BTreeMap<String, ActionId>,
     runtime_actions: Vec<Action>,
+    /// variables captured so far in the resolving action
+    captures: BTreeSet<String>,
 }
 
 impl RuntimeHelper {
-    fn new(con: Config) -> Self {
-        let actions = con
-            .actions
-            .into_iter()
-            .map(|x| x.actions.into_iter().map(|y| (y.event, y.tasks)))
-            .flatten()
-            .collect();
+    fn new(con: Config) -> Result<Self> {
+        let mut actions = BTreeMap::new();
+        for action in con.actions.into_iter().flat_map(|x| x.actions) {
+            // only hinge actions have an angle
+            let name = match (action.event.as_str(), action.threshold) {
+                ("on-hinge-above" | "on-hinge-below", Some(angle)) => {
+                    format!("{} {angle}", action.event)
+                }
+                ("on-hinge-above" | "on-hinge-below", None) => {
+                    return Err(anyhow!("{} should had an angle", action.event));
+                }
+                (_, Some(_)) => {
+                    return Err(anyhow!("Unexpected argument for action: {}", action.event));
+                }
+                (_, None) => action.event,
+            };
+            actions.insert(name, action.tasks);
+        }
         let variables = con
             .varibles
             .into_iter()
             .map(|x| x.variables.into_iter().map(|y| (y.name, y.value)))
             .flatten()
             .collect();
-        Self {
+        Ok(Self {
             actions,
             variables,
             settings: con.settings,
             action_id_map: Default::default(),
             runtime_actions: Default::default(),
-        }
+            captures: Default::default(),
+        })
     }
 
     fn get_action(&mut self, s: impl Into<String>) -> Option<Result<ActionId>> {
@@ -347,13 +1591,11 @@ impl RuntimeHelper {
                 // placeholder
                 self.runtime_actions.push(vec![]);
 
-                let tasks = self.actions.remove(vacant_entry.key())?;
+                let name = vacant_entry.key().clone();
+                let tasks = self.actions.remove(&name)?;
                 vacant_entry.insert(id);
 
-                let tasks = tasks
-                    .into_iter()
-                    .map(|s| self.resolve_task(s))
-                    .collect::<Result<Vec<_>>>();
+                let tasks = self.resolve_tasks(&name, tasks);
                 match tasks {
                     Ok(tasks) => self.runtime_actions[<_ as Into<usize>>::into(id)] = tasks,
                     Err(e) => return Some(Err(e)),
@@ -367,6 +1609,29 @@ impl RuntimeHelper {
         Some(Ok(id))
     }
 
+    /// anonymous action, e.g. bindings, `name` is only for log
+    fn add_action(&mut self, name: &str, tasks: Vec<config::Task>) -> Result<ActionId> {
+        let tasks = self.resolve_tasks(name, tasks)?;
+        let id = ActionId::from(self.runtime_actions.len());
+        self.runtime_actions.push(tasks);
+        Ok(id)
+    }
+
+    /// rotation action always exist, so calibration can follow it
+    fn get_rotate_action(&mut self, s: &str, mode: RotationMode) -> Result<ActionId> {
+        let id = match self.get_action(s).transpose()? {
+            Some(id) => id,
+            None => {
+                let id = ActionId::from(self.runtime_actions.len());
+                self.runtime_actions.push(vec![]);
+                self.action_id_map.insert(s.to_owned(), id);
+                id
+            }
+        };
+        self.runtime_actions[usize::from(id)].insert(0, Task::FollowRotation(mode));
+        Ok(id)
+    }
+
     fn get_variable(&self, s: impl Into<String>) -> Result<String> {
         let mut s = s.into();
         loop {
@@ -388,6 +1653,65 @@ impl RuntimeHelper {
         }
     }
 
+    fn resolve_arg(&self, s: VStr) -> Result<Arg> {
+        match s {
+            VStr::Config(c) if self.captures.contains(&c) => Ok(Arg::Variable(c)),
+            VStr::Config(c)
+                if !self.variables.contains_key(&c) && RUNTIME_VARIABLES.contains(&c.as_str()) =>
+            {
+                Ok(Arg::Variable(c))
+            }
+            s => self.resolve_vstr(s).map(Arg::Value),
+        }
+    }
+
+    /// in millisecond
+    fn resolve_timeout(&self, s: Option<VStr>) -> Result<Option<Duration>> {
+        s.map(|s| {
+            let s = self.resolve_vstr(s)?;
+            s.parse()
+                .map(Duration::from_millis)
+                .map_err(|_| anyhow!("Unknown value for cmd timeout: {s}"))
+        })
+        .transpose()
+    }
+
+    /// all variables visible to the task, e.g. `hinge-angle` is `ROTA_HINGE_ANGLE`
+    fn script_env(&self) -> Result<Vec<(String, Arg)>> {
+        let name = |s: &str| format!("ROTA_{}", s.to_uppercase().replace('-', "_"));
+        let mut env = self
+            .variables
+            .keys()
+            .map(|s| Ok((name(s), Arg::Value(self.get_variable(s)?))))
+            .collect::<Result<Vec<_>>>()?;
+        env.extend(
+            RUNTIME_VARIABLES
+                .iter()
+                .filter(|s| !self.variables.contains_key(**s))
+                .map(|s| s.to_string())
+                .chain(self.captures.iter().cloned())
+                .map(|s| (name(&s), Arg::Variable(s))),
+        );
+        Ok(env)
+    }
+
+    fn resolve_tasks(&mut self, name: &str, tasks: Vec<config::Task>) -> Result<Vec<Task>> {
+        // an action may be resolved while resolving another one
+        let captures = std::mem::take(&mut self.captures);
+        let tasks = tasks
+            .into_iter()
+            .map(|s| self.resolve_task(s))
+            .collect::<Result<Vec<_>>>();
+        self.captures = captures;
+        let mut tasks = tasks?;
+        for task in &mut tasks {
+            if let Task::Cmd(cmd) | Task::Capture(_, cmd) = task {
+                cmd.tag = name.to_owned();
+            }
+        }
+        Ok(tasks)
+    }
+
     fn resolve_task(&mut self, task: config::Task) -> Result<Task> {
         let task = match task {
             config::Task::Action(s) => {
@@ -397,23 +1721,121 @@ impl RuntimeHelper {
                         .ok_or_else(|| anyhow!("Cannot resolve action {name}"))??,
                 )
             }
-            config::Task::Cmd(ss) => Task::Cmd(
-                ss.into_iter()
-                    .map(|s| self.resolve_vstr(s))
+            config::Task::Capture(name, tasks) => {
+                let cmd = match <[_; 1]>::try_from(tasks) {
+                    Ok([task]) => match self.resolve_task(task)? {
+                        Task::Cmd(cmd) if !cmd.detach => cmd,
+                        _ => return Err(anyhow!("capture {name} should have a not detached cmd")),
+                    },
+                    Err(_) => return Err(anyhow!("capture {name} should have exactly one cmd")),
+                };
+                self.captures.insert(format!("{name}-status"));
+                self.captures.insert(name.clone());
+                Task::Capture(name, cmd)
+            }
+            config::Task::Cmd(ss, cwd, timeout, detach, stdin, env) => Task::Cmd(Cmd {
+                tag: String::new(),
+                args: ss
+                    .into_iter()
+                    .map(|s| self.resolve_arg(s))
                     .collect::<Result<Vec<_>>>()?,
-            ),
+                env: env
+                    .into_iter()
+                    .map(|env| Ok((env.name, self.resolve_arg(env.value)?)))
+                    .collect::<Result<Vec<_>>>()?,
+                cwd: cwd.map(|s| self.resolve_arg(s)).transpose()?,
+                timeout: self.resolve_timeout(timeout)?,
+                detach: detach.unwrap_or(false),
+                stdin: stdin.map(|s| self.resolve_arg(s)).transpose()?,
+            }),
+            config::Task::Script(script, cwd, timeout, detach, mut interpreter) => {
+                if interpreter.len() > 1 {
+                    return Err(anyhow!("script should had at most one interpreter"));
+                }
+                let mut args = match interpreter.pop() {
+                    Some(interpreter) => interpreter
+                        .args
+                        .into_iter()
+                        .map(|s| self.resolve_vstr(s))
+                        .collect::<Result<Vec<_>>>()?,
+                    None => self
+                        .settings
+                        .script_interpreter
+                        .clone()
+                        .unwrap_or_else(|| vec!["sh".to_owned(), "-c".to_owned()]),
+                };
+                if args.is_empty() {
+                    return Err(anyhow!("interpreter should had at least one args"));
+                }
+                // stdin is left to the script
+                args.push(script);
+                Task::Cmd(Cmd {
+                    tag: String::new(),
+                    args: args.into_iter().map(Arg::Value).collect(),
+                    env: self.script_env()?,
+                    cwd: cwd.map(|s| self.resolve_arg(s)).transpose()?,
+                    timeout: self.resolve_timeout(timeout)?,
+                    detach: detach.unwrap_or(false),
+                    stdin: None,
+                })
+            }
             config::Task::Rotation(s) => Task::Rotation(match self.resolve_vstr(s)?.as_str() {
-                "enable" => true,
-                "disable" => false,
+                "enable" => Some(true),
+                "disable" => Some(false),
+                "toggle" => None,
                 s => return Err(anyhow!("Unknown vaule for rotation: {s}")),
             }),
+            config::Task::Touchscreen(s) => {
+                Task::Touchscreen(match self.resolve_vstr(s)?.as_str() {
+                    "enable" => true,
+                    "disable" => false,
+                    s => return Err(anyhow!("Unknown vaule for touchscreen: {s}")),
+                })
+            }
+            config::Task::DeferRotation(s) => {
+                Task::DeferRotation(match self.resolve_vstr(s)?.as_str() {
+                    "disable" => None,
+                    s => {
+                        Some(Duration::from_millis(s.parse().map_err(|_| {
+                            anyhow!("Unknown value for defer rotation: {s}")
+                        })?))
+                    }
+                })
+            }
             config::Task::RotateCalibration(s) => {
                 Task::RotateCalibration(match self.resolve_vstr(s)?.as_str() {
-                    "normal" => RotationMode::Normal,
-                    "rotate90" => RotationMode::Rotate90,
-                    "rotate180" => RotationMode::Rotate180,
-                    "rotate270" => RotationMode::Rotate270,
-                    s => return Err(anyhow!("Uknown value for rotate calibration: {s}")),
+                    "follow" => None,
+                    s => Some(s.parse::<RotationMode>()?),
+                })
+            }
+            config::Task::MapToOutput(ss) => {
+                let ss = ss
+                    .into_iter()
+                    .map(|s| self.resolve_vstr(s))
+                    .collect::<Result<Vec<_>>>()?;
+                Task::MapToOutput(match ss.as_slice() {
+                    [s] if s == "none" => None,
+                    [x, y, width, height, layout_width, layout_height] => {
+                        let parse = |s: &String| {
+                            s.parse::<f32>()
+                                .ok()
+                                .filter(|value| value.is_finite())
+                                .ok_or_else(|| anyhow!("Unknown value for map to output: {s}"))
+                        };
+                        let size = |s: &String| match parse(s)? {
+                            value if value > 0. => Ok(value),
+                            _ => Err(anyhow!("map-to-output size should be positive: {s}")),
+                        };
+                        Some(Region {
+                            x: parse(x)?,
+                            y: parse(y)?,
+                            width: size(width)?,
+                            height: size(height)?,
+                            layout_width: size(layout_width)?,
+                            layout_height: size(layout_height)?,
+                        })
+                    }
+                    _ => return Err(anyhow!("map-to-output should had 6 args or \"none\"")),
                 })
             }
         };
@@ -424,24 +1846,189 @@ impl RuntimeHelper {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{env, fs, process::ExitStatus, time::Instant};
 
-    #[test]
-    fn rotate_matrix() {
-        assert_eq!(
-            calibration_matrix_product(DEFAULT_ROTATION, DEFAULT_ROTATION),
-            DEFAULT_ROTATION
-        );
-        assert_eq!(
-            calibration_matrix_product(DEFAULT_ROTATION, ROTATE_90),
-            ROTATE_90
+    fn sh(script: &str) -> Cmd {
+        Cmd {
+            tag: "test".to_owned(),
+            args: ["sh", "-c", script]
+                .map(|arg| Arg::Value(arg.to_owned()))
+                .into(),
+            env: Vec::new(),
+            cwd: None,
+            timeout: None,
+            detach: false,
+            stdin: None,
+        }
+    }
+
+    async fn run(cmd: &Cmd, variables: &BTreeMap<String, String>) -> (String, ExitStatus) {
+        let output = spawn(cmd, variables, None, None, true)
+            .await
+            .unwrap()
+            .unwrap();
+        (String::from_utf8(output.stdout).unwrap(), output.status)
+    }
+
+    #[tokio::test]
+    async fn spawn_options() {
+        let variables = BTreeMap::from([("name".to_owned(), "rota".to_owned())]);
+
+        let mut cmd = sh("printf %s \"$GREETING $NAME\"");
+        cmd.env = vec![
+            ("GREETING".to_owned(), Arg::Value("hi".to_owned())),
+            ("NAME".to_owned(), Arg::Variable("name".to_owned())),
+        ];
+        assert_eq!(run(&cmd, &variables).await.0, "hi rota");
+
+        let dir = env::temp_dir().canonicalize().unwrap();
+        let mut cmd = sh("pwd");
+        cmd.cwd = Some(Arg::Value(dir.to_str().unwrap().to_owned()));
+        assert_eq!(run(&cmd, &variables).await.0.trim(), dir.to_str().unwrap());
+
+        let mut cmd = sh("cat");
+        cmd.stdin = Some(Arg::Variable("name".to_owned()));
+        assert_eq!(run(&cmd, &variables).await.0, "rota");
+        // not inherited from rota
+        assert_eq!(run(&sh("cat"), &variables).await.0, "");
+
+        let (_, status) = run(&sh("exit 3"), &variables).await;
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn spawn_detach() {
+        let mut cmd = sh("sleep 1");
+        cmd.detach = true;
+        let start = Instant::now();
+        let output = spawn(&cmd, &BTreeMap::new(), None, None, false).await;
+        assert!(output.unwrap().is_none());
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn spawn_timeout() {
+        // the background sleep keeps stdout open until the whole group is killed
+        let mut cmd = sh("sleep 30 & echo $!; wait");
+        cmd.timeout = Some(Duration::from_millis(200));
+        let start = Instant::now();
+        let (stdout, status) = run(&cmd, &BTreeMap::new()).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+        // gone or a zombie waiting for init
+        let stat = fs::read_to_string(format!("/proc/{}/stat", stdout.trim()));
+        assert!(stat.is_err() || stat.unwrap().contains(") Z "));
+    }
+
+    fn action_runtime() -> ActionRuntime {
+        ActionRuntime {
+            action: mpsc::unbounded_channel().0,
+            rotation: watch::channel(true).0,
+            calibration: watch::channel(Calibration::default()).0,
+            touchscreen: watch::channel(true).0,
+            defer_rotation: watch::channel(None).0,
+            run_as: None,
+            session: None,
+            variables: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn spawn_error() {
+        let mut cmd = sh("true");
+        cmd.cwd = Some(Arg::Value("/nonexistent".to_owned()));
+        assert!(
+            spawn(&cmd, &BTreeMap::new(), None, None, false)
+                .await
+                .is_err()
         );
-        assert_eq!(
-            calibration_matrix_product(DEFAULT_ROTATION, ROTATE_180),
-            ROTATE_180
+        // logged, the following tasks still run
+        let task = Task::Cmd(cmd);
+        action_runtime()
+            .run_task(&task, &mut BTreeMap::new())
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn capture() {
+        let mut variables = BTreeMap::from([("out".to_owned(), "stale".to_owned())]);
+        let mut action_rt = action_runtime();
+        let task = Task::Capture("out".to_owned(), sh("printf ' rota\\n\\n'; exit 3"));
+        action_rt.run_task(&task, &mut variables).await.unwrap();
+        assert_eq!(variables["out"], "rota");
+        assert_eq!(variables["out-status"], "3");
+
+        let task = Task::Capture("out".to_owned(), sh("kill -9 $$"));
+        action_rt.run_task(&task, &mut variables).await.unwrap();
+        assert_eq!(variables["out"], "");
+        assert_eq!(variables["out-status"], "137");
+
+        // cannot spawn
+        let mut cmd = sh("echo rota");
+        cmd.cwd = Some(Arg::Value("/nonexistent".to_owned()));
+        variables.insert("out".to_owned(), "stale".to_owned());
+        let task = Task::Capture("out".to_owned(), cmd);
+        action_rt.run_task(&task, &mut variables).await.unwrap();
+        assert_eq!(variables["out"], "");
+        assert_eq!(variables["out-status"], "-1");
+
+        // skipped without active session
+        action_rt.run_as = Some(RunAs::ActiveSession);
+        variables.insert("out".to_owned(), "stale".to_owned());
+        let task = Task::Capture("out".to_owned(), sh("echo rota"));
+        action_rt.run_task(&task, &mut variables).await.unwrap();
+        assert_eq!(variables["out"], "");
+        assert_eq!(variables["out-status"], "-1");
+    }
+    #[tokio::test]
+    async fn session_env() {
+        let mut variables = BTreeMap::new();
+        let mut action_rt = action_runtime();
+        // SAFETY: no argument
+        let uid = unsafe { libc::geteuid() };
+        action_rt.session = Some(ActiveSession {
+            id: "1".to_owned(),
+            uid,
+            runtime_dir: Some("/nonexistent".to_owned()),
+            display: Some(":1".to_owned()),
+        });
+        // without `run-as`
+        let task = Task::Capture(
+            "env".to_owned(),
+            sh("printf %s \"$XDG_RUNTIME_DIR $DISPLAY\""),
         );
+        action_rt.run_task(&task, &mut variables).await.unwrap();
+        assert_eq!(variables["env"], "/nonexistent :1");
+    }
+    #[tokio::test]
+    async fn run_action_inline() {
+        let (action, mut action_r) = mpsc::unbounded_channel();
+        let action_rt = ActionRuntime {
+            action,
+            ..action_runtime()
+        };
+        let dir = env::temp_dir().join(format!("rota-inline-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let log = |name: &str| Task::Cmd(sh(&format!("echo {name} >> {}/log", dir.display())));
+        let actions = vec![
+            vec![
+                Task::Action(1.into()),
+                log("suspend"),
+                Task::Action(2.into()),
+            ],
+            vec![log("first"), Task::Action(0.into())],
+            vec![log("second")],
+        ];
+        action_rt
+            .run_action_inline(&actions, 0.into())
+            .await
+            .unwrap();
         assert_eq!(
-            calibration_matrix_product(DEFAULT_ROTATION, ROTATE_270),
-            ROTATE_270
+            fs::read_to_string(dir.join("log")).unwrap(),
+            "suspend\nfirst\nsecond\n"
         );
+        // nothing is left for later
+        assert!(action_r.try_recv().is_err());
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
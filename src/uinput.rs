@@ -0,0 +1,118 @@
+use libc::{Ioctl, c_int, input_event, input_id, ioctl, timeval, uinput_setup};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    mem::size_of,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    slice,
+};
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_SW: u16 = 0x05;
+pub const SYN_REPORT: u16 = 0;
+pub const SW_LID: u16 = 0x00;
+pub const SW_TABLET_MODE: u16 = 0x01;
+
+const BUS_VIRTUAL: u16 = 0x06;
+
+const UI_DEV_CREATE: Ioctl = 0x5501;
+const UI_DEV_DESTROY: Ioctl = 0x5502;
+/// `_IOW('U', 3, struct uinput_setup)`
+const UI_DEV_SETUP: Ioctl = 0x405c5503;
+const UI_SET_EVBIT: Ioctl = 0x40045564;
+const UI_SET_KEYBIT: Ioctl = 0x40045565;
+const UI_SET_SWBIT: Ioctl = 0x4004556d;
+
+/// a virtual input device, destroyed on drop
+#[derive(Debug)]
+pub struct VirtualDevice {
+    file: File,
+}
+
+impl VirtualDevice {
+    /// `codes` is `(event type, code)` pairs, e.g. `(EV_SW, SW_TABLET_MODE)`
+    pub fn new(name: &str, codes: &[(u16, u16)]) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")?;
+        let device = Self { file };
+
+        let mut types = codes.iter().map(|(ty, _)| *ty).collect::<Vec<_>>();
+        types.sort();
+        types.dedup();
+        for ty in types {
+            device.ioctl(UI_SET_EVBIT, ty as c_int)?;
+        }
+        for &(ty, code) in codes {
+            let request = match ty {
+                EV_KEY => UI_SET_KEYBIT,
+                EV_SW => UI_SET_SWBIT,
+                _ => return Err(io::Error::other(format!("Unsupported event type: {ty}"))),
+            };
+            device.ioctl(request, code as c_int)?;
+        }
+
+        // SAFETY: plain C struct, all zero is valid
+        let mut setup: uinput_setup = unsafe { std::mem::zeroed() };
+        setup.id = input_id {
+            bustype: BUS_VIRTUAL,
+            vendor: 0,
+            product: 0,
+            version: 1,
+        };
+        for (dst, src) in setup.name.iter_mut().zip(name.bytes().take(79)) {
+            *dst = src as _;
+        }
+        // SAFETY: `setup` outlives the call
+        if unsafe { ioctl(device.file.as_raw_fd(), UI_DEV_SETUP, &setup) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: no argument
+        if unsafe { ioctl(device.file.as_raw_fd(), UI_DEV_CREATE) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(device)
+    }
+
+    fn ioctl(&self, request: Ioctl, value: c_int) -> io::Result<()> {
+        // SAFETY: the argument is passed by value
+        match unsafe { ioctl(self.file.as_raw_fd(), request, value) } {
+            ret if ret < 0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    /// write events followed by `SYN_REPORT`
+    pub fn emit(&mut self, events: &[(u16, u16, i32)]) -> io::Result<()> {
+        let events = events
+            .iter()
+            .chain([&(EV_SYN, SYN_REPORT, 0)])
+            .map(|&(type_, code, value)| input_event {
+                time: timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_,
+                code,
+                value,
+            })
+            .collect::<Vec<_>>();
+        // SAFETY: `input_event` is a plain C struct without padding
+        let buf = unsafe {
+            slice::from_raw_parts(
+                events.as_ptr().cast::<u8>(),
+                events.len() * size_of::<input_event>(),
+            )
+        };
+        self.file.write_all(buf)
+    }
+}
+
+impl Drop for VirtualDevice {
+    fn drop(&mut self) {
+        // SAFETY: no argument
+        unsafe { ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY) };
+    }
+}
@@ -0,0 +1,150 @@
+use libc::{c_char, c_int, gid_t, passwd, uid_t};
+use std::{
+    ffi::{CStr, CString},
+    io,
+    path::Path,
+    ptr,
+};
+use tokio::process::Command;
+
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/bin:/usr/sbin:/bin:/sbin";
+
+/// a system user to run command as
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    pub name: String,
+    pub uid: uid_t,
+    pub gid: gid_t,
+    /// supplementary groups, include the primary group
+    pub groups: Vec<gid_t>,
+    pub home: String,
+    pub shell: String,
+}
+
+impl User {
+    /// user name or uid
+    pub fn new(s: &str) -> io::Result<Self> {
+        match s.parse() {
+            Ok(uid) => Self::from_uid(uid),
+            Err(_) => Self::from_name(s),
+        }
+    }
+
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        let name = CString::new(name).map_err(io::Error::other)?;
+        // SAFETY: `name` outlives the call
+        lookup(|pwd, buf, len, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), pwd, buf, len, result)
+        })
+    }
+
+    pub fn from_uid(uid: uid_t) -> io::Result<Self> {
+        // SAFETY: no pointer argument other than the buffers
+        lookup(|pwd, buf, len, result| unsafe { libc::getpwuid_r(uid, pwd, buf, len, result) })
+    }
+
+    /// # Safety
+    ///
+    /// the strings of `pwd` should be valid
+    unsafe fn from_passwd(pwd: &passwd) -> Self {
+        let string = |s: *const c_char| unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+        let name = unsafe { CStr::from_ptr(pwd.pw_name) };
+
+        let mut groups = vec![0 as gid_t; 64];
+        loop {
+            let mut len = groups.len() as c_int;
+            // SAFETY: `len` is the size of `groups`
+            if unsafe {
+                libc::getgrouplist(name.as_ptr(), pwd.pw_gid, groups.as_mut_ptr(), &mut len)
+            } >= 0
+            {
+                groups.truncate(len as usize);
+                break;
+            }
+            // `len` is the required size now
+            groups.resize((len as usize).max(groups.len() * 2), 0);
+        }
+
+        Self {
+            name: name.to_string_lossy().into_owned(),
+            uid: pwd.pw_uid,
+            gid: pwd.pw_gid,
+            groups,
+            home: string(pwd.pw_dir),
+            shell: string(pwd.pw_shell),
+        }
+    }
+
+    /// `/run/user/{uid}` if exists
+    pub fn runtime_dir(&self) -> Option<String> {
+        let dir = format!("/run/user/{}", self.uid);
+        Path::new(&dir).is_dir().then_some(dir)
+    }
+
+    /// clear the environment and drop to the user before exec
+    pub fn apply(&self, command: &mut Command) {
+        command
+            .env_clear()
+            .env("HOME", &self.home)
+            .env("USER", &self.name)
+            .env("LOGNAME", &self.name)
+            .env("SHELL", &self.shell)
+            .env("PATH", DEFAULT_PATH);
+        if let Some(dir) = self.runtime_dir() {
+            command.env("XDG_RUNTIME_DIR", dir);
+        }
+        if Path::new(&self.home).is_dir() {
+            command.current_dir(&self.home);
+        }
+
+        let (uid, gid, groups) = (self.uid, self.gid, self.groups.clone());
+        // SAFETY: only async-signal-safe syscalls, nothing is allocated
+        unsafe {
+            command.pre_exec(move || {
+                // groups must be set before losing root
+                if libc::setgroups(groups.len(), groups.as_ptr()) < 0
+                    || libc::setgid(gid) < 0
+                    || libc::setuid(uid) < 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// call `getpw*_r` with a growing buffer
+fn lookup(
+    f: impl Fn(*mut passwd, *mut c_char, usize, *mut *mut passwd) -> c_int,
+) -> io::Result<User> {
+    let mut buf = vec![0 as c_char; 1024];
+    loop {
+        // SAFETY: plain C struct, all zero is valid
+        let mut pwd: passwd = unsafe { std::mem::zeroed() };
+        let mut result = ptr::null_mut();
+        match f(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if result.is_null() => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "Cannot find user"));
+            }
+            // SAFETY: the strings point into `buf`
+            0 => return Ok(unsafe { User::from_passwd(&pwd) }),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_root() {
+        let root = User::new("root").unwrap();
+        assert_eq!(root.uid, 0);
+        assert!(root.groups.contains(&root.gid));
+        assert_eq!(User::new("0").unwrap(), root);
+        assert!(User::new("no-such-user-for-rota").is_err());
+    }
+}
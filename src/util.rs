@@ -0,0 +1,24 @@
+use futures::{Stream, StreamExt};
+use std::future::pending;
+use tokio::sync::watch;
+
+/// pending forever if there is no stream
+pub async fn next_some<S: Stream + Unpin>(stream: &mut Option<S>) -> Option<S::Item> {
+    match stream {
+        Some(stream) => stream.next().await,
+        None => pending().await,
+    }
+}
+
+/// the new value, pending if `None`
+pub async fn changed_some<T: Clone>(
+    receiver: &mut Option<watch::Receiver<T>>,
+) -> Result<T, watch::error::RecvError> {
+    match receiver {
+        Some(receiver) => {
+            receiver.changed().await?;
+            Ok(receiver.borrow_and_update().clone())
+        }
+        None => pending().await,
+    }
+}